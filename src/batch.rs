@@ -0,0 +1,122 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{simulate_match, strategies, MatchResult, SimConfig};
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Un combate sin gráficos por jugar: las dos estrategias registradas (robot 1 y robot 2) y la
+// configuración con la que se enfrentan
+#[derive(Clone, Debug)]
+pub struct Job<'a> {
+    pub names: [&'a str; 2],
+    pub config: SimConfig,
+}
+
+// Juega todos los combates de `jobs` repartidos entre `threads` hilos (0 significa uno por núcleo)
+// y devuelve sus resultados en el mismo orden que `jobs`
+//
+// Los hilos no se reparten los combates de antemano: cada uno coge el siguiente que quede en
+// cuanto termina el suyo, así ninguno se queda parado mientras otro tiene varios combates largos
+// pendientes. Como cada combate solo depende de su configuración (semilla incluida) y los
+// resultados se colocan por su posición, sale exactamente lo mismo con cualquier número de hilos
+pub fn run_batch(jobs: &[Job], threads: usize) -> Result<Vec<MatchResult>, String> {
+    for job in jobs {
+        for name in job.names {
+            strategies::by_name(name)?; // comprobamos que existan antes de empezar
+        }
+    }
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let next = AtomicUsize::new(0); // el siguiente combate que nadie ha cogido todavía
+    let mut results: Vec<Option<MatchResult>> = vec![None; jobs.len()];
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.min(jobs.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let n = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(n) else { break };
+                        // las estrategias ya se comprobaron
+                        let [strat1, strat2] = job.names.map(|n| strategies::by_name(n).unwrap());
+                        done.push((n, simulate_match(strat1, strat2, &job.config)));
+                    }
+                    done
+                })
+            })
+            .collect();
+        for worker in workers {
+            for (n, result) in worker.join().unwrap() {
+                results[n] = Some(result);
+            }
+        }
+    });
+    Ok(results.into_iter().map(Option::unwrap).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RoundEnd;
+
+    // Unos cuantos combates distintos: todas las parejas de estrategias, con semillas, salidas al
+    // azar y ruido en los sensores, para que cualquier dependencia del orden se notara
+    fn jobs() -> Vec<Job<'static>> {
+        let names = strategies::names();
+        let mut jobs = Vec::new();
+        for (n, &first) in names.iter().enumerate() {
+            for &second in &names {
+                jobs.push(Job {
+                    names: [first, second],
+                    config: SimConfig {
+                        seed: n as u64,
+                        starts: "random, shikiri".to_string(),
+                        noise_std: 0.01,
+                        dropout: 0.05,
+                        ..SimConfig::default()
+                    },
+                });
+            }
+        }
+        jobs
+    }
+
+    type Summary = (Option<usize>, Vec<(RoundEnd, u32)>);
+
+    // Lo que tiene que coincidir de cada combate: el ganador y, ronda a ronda, cómo acabó y
+    // cuántos pasos duró
+    fn summary(results: &[MatchResult]) -> Vec<Summary> {
+        results
+            .iter()
+            .map(|result| {
+                let rounds = result
+                    .rounds
+                    .iter()
+                    .map(|round| (round.cause, round.ticks))
+                    .collect();
+                (result.winner, rounds)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_results_with_any_thread_count() {
+        let jobs = jobs();
+        let single = summary(&run_batch(&jobs, 1).unwrap());
+        assert_eq!(single.len(), jobs.len());
+        for threads in [2, 3, 8] {
+            assert_eq!(summary(&run_batch(&jobs, threads).unwrap()), single);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::{f32::consts::PI, fmt};
+
+use crate::{ratings::Z_95, run_batch, Job, MatchResult, Rng, SimConfig};
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Lo que ha sacado la estrategia A contra la B en un grupo de combates
+#[derive(Clone, Debug)]
+pub struct Tally {
+    pub label: String, // de qué combates se trata (todos o los de una salida)
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl Tally {
+    pub(crate) fn new(label: &str) -> Tally {
+        Tally {
+            label: label.to_string(),
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    // La proporción de `count` sobre todos los combates, con el intervalo en el que está la de
+    // verdad con un 95% de confianza (intervalo de Wilson, que no se vuelve loco con pocos
+    // combates ni con proporciones cerca de 0 o de 1)
+    pub fn rate(&self, count: u32) -> (f32, f32, f32) {
+        let n = self.samples().max(1) as f32;
+        let p = count as f32 / n;
+        let z = Z_95 as f32;
+        let z2 = z * z;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let half = z * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+        (p, (center - half).max(0.0), (center + half).min(1.0))
+    }
+
+    // Apunta un combate: `Some(true)` si ha ganado A, `Some(false)` si ha ganado B y `None` si
+    // han empatado
+    pub(crate) fn add(&mut self, score: Option<bool>) {
+        match score {
+            Some(true) => self.wins += 1,
+            Some(false) => self.losses += 1,
+            None => self.draws += 1,
+        }
+    }
+}
+
+// El resultado de comparar dos estrategias: el total y el desglose por salida
+pub struct Comparison {
+    pub names: [String; 2],
+    pub noise: [f32; 4], // el ruido de los sensores usado (ver `sensor_noise`)
+    pub total: Tally,
+    pub by_start: Vec<Tally>,
+}
+
+// Enfrenta a las estrategias `names` en `samples` combates, cada uno con lo que una simulación
+// determinista deja fijo cambiado al azar (a partir de la semilla de `config`):
+//
+// - la salida: se van turnando las de `config.starts` (cada combate usa una sola en todas sus
+//   rondas) y cada robot se coloca desplazado hasta `jitter` veces su lado y torcido hasta
+//   `jitter` medias vueltas
+// - el ruido de los sensores: cada combate tiene su propia semilla, y si `config` no pone ningún
+//   ruido se usa uno proporcional a `jitter` (ver `sensor_noise`)
+// - la física: cada parámetro de cada robot se multiplica por un factor entre `1 - jitter` y
+//   `1 + jitter`
+//
+// Las estrategias también se turnan como robot 1 y robot 2. Los combates se reparten entre
+// `threads` hilos (ver `run_batch`). Tras cada combate llama a `on_match` con las dos estrategias
+// (en el orden en que han jugado) y el resultado
+pub fn compare(
+    names: [&str; 2],
+    samples: u32,
+    jitter: f32,
+    threads: usize,
+    config: &SimConfig,
+    mut on_match: impl FnMut([&str; 2], &MatchResult),
+) -> Result<Comparison, String> {
+    if samples == 0 {
+        return Err("hace falta al menos un combate (`--samples`)".to_string());
+    }
+    check_jitter(jitter)?;
+    let (jobs, seats): (Vec<Job>, Vec<usize>) =
+        sample_jobs(names, samples, jitter, config).into_iter().unzip();
+    let results = run_batch(&jobs, threads)?;
+
+    let mut total = Tally::new("todas");
+    let starts: Vec<&str> = config.starts.split(',').map(str::trim).collect();
+    let mut by_start: Vec<Tally> = starts.iter().map(|start| Tally::new(start)).collect();
+    for (n, (job, result)) in jobs.iter().zip(&results).enumerate() {
+        on_match(job.names, result);
+        let score = result.winner.map(|winner| winner == seats[n]);
+        total.add(score);
+        by_start[n % starts.len()].add(score);
+    }
+    Ok(Comparison {
+        names: names.map(str::to_string),
+        noise: sensor_noise(config, jitter),
+        total,
+        by_start,
+    })
+}
+
+// `jitter` tiene que dejar todos los factores de la física por encima de 0: con un factor negativo
+// la masa o el radio de las ruedas dejan de tener sentido (y la simulación da NaN)
+pub(crate) fn check_jitter(jitter: f32) -> Result<(), String> {
+    if (0.0..1.0).contains(&jitter) {
+        Ok(())
+    } else {
+        Err(format!("`--jitter` tiene que estar entre 0 y 1 (sin llegar a 1), es {}", jitter))
+    }
+}
+
+// Los combates de una comparación entre `names` (ver `compare`), cada uno con el robot que lleva A
+// (que es el robot 2 cuando se han cambiado de sitio)
+pub(crate) fn sample_jobs<'a>(
+    names: [&'a str; 2],
+    samples: u32,
+    jitter: f32,
+    config: &SimConfig,
+) -> Vec<(Job<'a>, usize)> {
+    let starts: Vec<&str> = config.starts.split(',').map(str::trim).collect();
+    let rng = Rng::new(config.seed);
+    (0..samples as usize)
+        .map(|n| {
+            let a = (n / starts.len()) % 2;
+            let job = Job {
+                names: if a == 1 { [names[1], names[0]] } else { names },
+                config: sample(config, starts[n % starts.len()], jitter, &mut rng.fork(n as u64)),
+            };
+            (job, a)
+        })
+        .collect()
+}
+
+// El ruido de los sensores en los combates de una comparación, como
+// `[noise_std, quantization, dropout, false_positive]`: el de `config` si tiene alguno y, si son
+// sensores perfectos, uno que crece con `jitter` (con `jitter` 0.1, 2 cm de ruido en un robot de
+// 20 cm, un 5% de lecturas perdidas y un 1% de lecturas falsas)
+fn sensor_noise(config: &SimConfig, jitter: f32) -> [f32; 4] {
+    let noise = [
+        config.noise_std,
+        config.quantization,
+        config.dropout,
+        config.false_positive,
+    ];
+    if noise.iter().any(|&x| x > 0.0) {
+        noise
+    } else {
+        [jitter * config.sumo_size, 0.0, jitter / 2.0, jitter / 10.0]
+    }
+}
+
+// La configuración de un combate de la comparación, a partir de la de base
+fn sample(base: &SimConfig, start: &str, jitter: f32, rng: &mut Rng) -> SimConfig {
+    let mut config = base.clone();
+    config.seed = rng.next_u64();
+    config.starts = start.to_string();
+    config.start_jitter = jitter * config.sumo_size;
+    config.heading_jitter = jitter * PI;
+    [
+        config.noise_std,
+        config.quantization,
+        config.dropout,
+        config.false_positive,
+    ] = sensor_noise(base, jitter);
+    for robot in config.robots.iter_mut() {
+        for param in [
+            &mut robot.max_speed,
+            &mut robot.accel,
+            &mut robot.decel,
+            &mut robot.motor_lag,
+            &mut robot.mass,
+            &mut robot.wheel_friction,
+            &mut robot.lateral_friction,
+            &mut robot.torque,
+            &mut robot.wheel_radius,
+        ] {
+            *param *= rng.range(1.0 - jitter, 1.0 + jitter);
+        }
+    }
+    config
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "A = {}, B = {} ({} combates)",
+            self.names[0],
+            self.names[1],
+            self.total.samples()
+        )?;
+        let [noise_std, quantization, dropout, false_positive] = self.noise;
+        writeln!(
+            f,
+            "ruido de los sensores: noise_std {:.3} m, quantization {:.3} m, dropout {:.3}, \
+             false_positive {:.3}",
+            noise_std, quantization, dropout, false_positive
+        )?;
+        let width = self.by_start.iter().map(|t| t.label.len()).chain([6]).max().unwrap_or(6);
+        writeln!(
+            f,
+            "{:<width$}  {:>8}  {:>20}  {:>20}  {:>20}",
+            "salida", "combates", "gana A", "gana B", "empates"
+        )?;
+        for tally in [&self.total].into_iter().chain(&self.by_start) {
+            let cell = |count| {
+                let (p, low, high) = tally.rate(count);
+                format!("{:.1}% [{:.1}, {:.1}]", 100.0 * p, 100.0 * low, 100.0 * high)
+            };
+            writeln!(
+                f,
+                "{:<width$}  {:>8}  {:>20}  {:>20}  {:>20}",
+                tally.label,
+                tally.samples(),
+                cell(tally.wins),
+                cell(tally.losses),
+                cell(tally.draws)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::fs;
+
+use crate::{parse_starts, robot::RobotParams, sensors};
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Convierte el texto de un parámetro en su valor
+pub(crate) fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("valor inválido para `{}`: `{}`", key, value))
+}
+
+// Como `parse`, pero solo acepta números de 0 en adelante (velocidades, aceleraciones...)
+pub(crate) fn parse_non_negative(key: &str, value: &str) -> Result<f32, String> {
+    match parse::<f32>(key, value)? {
+        x if x >= 0.0 => Ok(x),
+        _ => Err(format!("`{}` no puede ser negativo: `{}`", key, value)),
+    }
+}
+
+// Como `parse`, pero solo acepta números mayores que 0 (masas, tamaños...)
+pub(crate) fn parse_positive(key: &str, value: &str) -> Result<f32, String> {
+    match parse::<f32>(key, value)? {
+        x if x > 0.0 => Ok(x),
+        _ => Err(format!("`{}` tiene que ser mayor que 0: `{}`", key, value)),
+    }
+}
+
+// Parámetros de la simulación
+//
+// Antes eran constantes, ahora se pueden cambiar sin recompilar: desde un fichero de configuración
+// (una `clave = valor` por línea, ver `load`) o desde la línea de comandos (`--clave valor`)
+//
+// Todo va en unidades del Sistema Internacional (metros, segundos, kilos, radianes), así se
+// pueden copiar tal cual los números de las hojas de datos de un robot de verdad
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    pub rounds: u8,                  // rondas del combate (al mejor de `rounds`)
+    pub dt: f32,                     // segundos que representa cada paso de la simulación (cada
+                                     // cuánto leen los sensores y deciden las estrategias)
+    pub substeps: u32,               // en cuántos trozos se divide cada paso para mover los
+                                     // robots y resolver los choques
+    pub round_time: f32,             // segundos tras los que una ronda se declara empate
+    pub start_delay: f32,            // segundos de espera tras la señal de salida, en los que los
+                                     // motores no obedecen (las reglas piden 5)
+    pub false_start: bool,           // si mover los motores durante la espera de salida hace
+                                     // perder la ronda
+    pub extension: bool,             // si tras las rondas nadie ha ganado, se juega una ronda más
+                                     // (prórroga)
+    pub tatami_size: f32,            // radio del "tatami" (circulo de lucha, m)
+    pub sumo_size: f32,              // lado de un robot (son cuadrados, m)
+    pub x_init_pos: f32,             // coordenada x del estado inicial de los robots (m)
+    pub starts: String,              // cómo empieza cada ronda, una lista de salidas separadas
+                                     // por comas que se va repitiendo (ver `Start`)
+    pub start_jitter: f32,           // cuánto se puede apartar cada robot de su sitio en las
+                                     // salidas fijas (m)
+    pub heading_jitter: f32,         // cuánto se puede torcer cada robot en las salidas fijas
+                                     // (radianes)
+    pub drawing_boundary_size: f32,  // sensibilidad del adaptador gráfico (en casillas)
+    pub sensor_half_angle: f32,      // mitad de la apertura del cono del sensor (radianes)
+    pub sensor_range: f32,           // alcance máximo del sensor (m)
+    pub sensors: [String; 2],        // sensores de cada robot (ver `sensors::layout`)
+    pub line_sensors: [String; 2],   // sensores de línea de cada robot (ver
+                                     // `sensors::line_layout`)
+    pub border_width: f32,           // ancho del borde blanco del tatami (m)
+    pub seed: u64,                   // semilla de todo lo aleatorio de la simulación
+    pub noise_std: f32,              // | cómo se estropean las lecturas de los sensores (ver
+    pub quantization: f32,           // | `sensors::SensorNoise`), todo a 0 significa sensores
+    pub latency: f32,                // | perfectos
+    pub dropout: f32,                // |
+    pub false_positive: f32,         // |
+    pub robots: [RobotParams; 2],    // características físicas de cada robot
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            rounds: 3,
+            dt: 0.05,
+            substeps: 4,
+            round_time: 60.0,
+            start_delay: 5.0,
+            false_start: false,
+            extension: true,
+            tatami_size: 0.77, // la categoría `standard`
+            sumo_size: 0.2,
+            x_init_pos: 0.2,
+            starts: "shikiri, side_by_side, back_to_back".to_string(),
+            start_jitter: 0.0,
+            heading_jitter: 0.0,
+            drawing_boundary_size: 0.5,
+            sensor_half_angle: 0.26, // unos 15º, como un HC-SR04
+            sensor_range: 1.5,
+            sensors: ["front".to_string(), "front".to_string()],
+            line_sensors: ["front".to_string(), "front".to_string()],
+            border_width: 0.05,
+            seed: 0,
+            noise_std: 0.0,
+            quantization: 0.0,
+            latency: 0.0,
+            dropout: 0.0,
+            false_positive: 0.0,
+            robots: [RobotParams::default(), RobotParams::default()],
+        }
+    }
+}
+
+impl SimConfig {
+    // Configuraciones con las medidas de las categorías reales (aquí en centímetros, en el código
+    // en metros):
+    //
+    // | categoría  | diámetro del tatami | borde | lado del robot | separación líneas | masa  |
+    // |------------|---------------------|-------|----------------|-------------------|-------|
+    // | `standard` | 154                 | 5     | 20             | 20                | 3 kg  |
+    // | `mini`     | 77                  | 2.5   | 10             | 10                | 500 g |
+    // | `micro`    | 38.5                | 1.25  | 5              | 5                 | 100 g |
+    //
+    // Los robots empiezan justo detrás de su línea de salida y pesan el máximo permitido.
+    // `classic` son las proporciones de siempre del simulador (un tatami de 2 m de radio con robots
+    // de 25 cm que empiezan en su borde)
+    pub fn preset(name: &str) -> Option<SimConfig> {
+        let (tatami_size, border_width, sumo_size, shikiri, mass) = match name {
+            "classic" => (2.0, 0.125, 0.25, 1.75, 3.0),
+            "standard" => (0.77, 0.05, 0.2, 0.2, 3.0),
+            "mini" => (0.385, 0.025, 0.1, 0.1, 0.5),
+            "micro" => (0.1925, 0.0125, 0.05, 0.05, 0.1),
+            _ => return None,
+        };
+        let robot = RobotParams {
+            mass,
+            ..RobotParams::default()
+        };
+        Some(SimConfig {
+            robots: [robot.clone(), robot],
+            tatami_size,
+            border_width,
+            sumo_size,
+            x_init_pos: (shikiri + sumo_size) / 2.0,
+            sensor_range: tatami_size * 2.0, // todo el tatami
+            ..SimConfig::default()
+        })
+    }
+
+    // Cambia un parámetro a partir de su nombre y su valor escrito como texto, así nos sirve
+    // tanto para el fichero como para la línea de comandos
+    //
+    // Los parámetros de cada robot (sensores y `RobotParams`) se cambian para los dos a la vez
+    // (`max_speed`) o para uno solo añadiendo `_1` o `_2` al nombre (`max_speed_2`)
+    //
+    // `preset` es especial: sustituye toda la configuración por la de la categoría
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let key = key.replace('-', "_");
+        let (robot_key, robots) = match (key.strip_suffix("_1"), key.strip_suffix("_2")) {
+            (Some(base), _) => (base, 0..1),
+            (_, Some(base)) => (base, 1..2),
+            _ => (key.as_str(), 0..2),
+        };
+        let mut robot_param = false;
+        for robot in robots {
+            robot_param = match robot_key {
+                "sensors" => {
+                    sensors::layout(value, self)?; // comprobamos que se entienda
+                    self.sensors[robot] = value.to_string();
+                    true
+                }
+                "line_sensors" => {
+                    sensors::line_layout(value, self)?;
+                    self.line_sensors[robot] = value.to_string();
+                    true
+                }
+                _ => self.robots[robot].set(robot_key, value)?,
+            };
+            if !robot_param {
+                break;
+            }
+        }
+        if robot_param {
+            return Ok(());
+        }
+
+        match key.as_str() {
+            "preset" => {
+                *self = SimConfig::preset(value)
+                    .ok_or_else(|| format!("no existe la categoría `{}`", value))?
+            }
+            // la prórroga es una ronda más, que también tiene que caber en un `u8`
+            "rounds" => {
+                self.rounds = match parse(&key, value)? {
+                    n @ 1..=254 => n,
+                    _ => return Err(format!("`{}` tiene que estar entre 1 y 254: `{}`", key, value)),
+                }
+            }
+            "dt" => self.dt = parse_positive(&key, value)?,
+            "substeps" => {
+                self.substeps = match parse(&key, value)? {
+                    0 => return Err(format!("`{}` tiene que ser mayor que 0: `{}`", key, value)),
+                    n => n,
+                }
+            }
+            "round_time" => self.round_time = parse_positive(&key, value)?,
+            "start_delay" => self.start_delay = parse_non_negative(&key, value)?,
+            "false_start" => self.false_start = parse(&key, value)?,
+            "extension" => self.extension = parse(&key, value)?,
+            "tatami_size" => self.tatami_size = parse_positive(&key, value)?,
+            "sumo_size" => self.sumo_size = parse_positive(&key, value)?,
+            "x_init_pos" => self.x_init_pos = parse_non_negative(&key, value)?,
+            "starts" => {
+                parse_starts(value)?; // comprobamos que se entienda
+                self.starts = value.to_string();
+            }
+            "start_jitter" => self.start_jitter = parse(&key, value)?,
+            "heading_jitter" => self.heading_jitter = parse(&key, value)?,
+            "drawing_boundary_size" => self.drawing_boundary_size = parse(&key, value)?,
+            "sensor_half_angle" => self.sensor_half_angle = parse_non_negative(&key, value)?,
+            "sensor_range" => self.sensor_range = parse_positive(&key, value)?,
+            "border_width" => self.border_width = parse_non_negative(&key, value)?,
+            "seed" => self.seed = parse(&key, value)?,
+            "noise_std" => self.noise_std = parse(&key, value)?,
+            "quantization" => self.quantization = parse(&key, value)?,
+            "latency" => self.latency = parse(&key, value)?,
+            "dropout" => self.dropout = parse(&key, value)?,
+            "false_positive" => self.false_positive = parse(&key, value)?,
+            _ => return Err(format!("parámetro desconocido: `{}`", key)),
+        };
+        Ok(())
+    }
+
+    // Todos los parámetros como pares `(clave, valor)`, en el mismo formato que entiende `set`
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries: Vec<(String, String)> = [
+            ("rounds", self.rounds.to_string()),
+            ("dt", self.dt.to_string()),
+            ("substeps", self.substeps.to_string()),
+            ("round_time", self.round_time.to_string()),
+            ("start_delay", self.start_delay.to_string()),
+            ("false_start", self.false_start.to_string()),
+            ("extension", self.extension.to_string()),
+            ("tatami_size", self.tatami_size.to_string()),
+            ("sumo_size", self.sumo_size.to_string()),
+            ("x_init_pos", self.x_init_pos.to_string()),
+            ("starts", self.starts.clone()),
+            ("start_jitter", self.start_jitter.to_string()),
+            ("heading_jitter", self.heading_jitter.to_string()),
+            ("drawing_boundary_size", self.drawing_boundary_size.to_string()),
+            ("sensor_half_angle", self.sensor_half_angle.to_string()),
+            ("sensor_range", self.sensor_range.to_string()),
+            ("border_width", self.border_width.to_string()),
+            ("seed", self.seed.to_string()),
+            ("noise_std", self.noise_std.to_string()),
+            ("quantization", self.quantization.to_string()),
+            ("latency", self.latency.to_string()),
+            ("dropout", self.dropout.to_string()),
+            ("false_positive", self.false_positive.to_string()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+        for robot in 0..2 {
+            let suffixed = |key: &str| format!("{}_{}", key, robot + 1);
+            entries.push((suffixed("sensors"), self.sensors[robot].clone()));
+            entries.push((suffixed("line_sensors"), self.line_sensors[robot].clone()));
+            for (key, value) in self.robots[robot].entries() {
+                entries.push((suffixed(key), value));
+            }
+        }
+        entries
+    }
+
+    // Aplica un fichero de configuración encima de la configuración actual
+    //
+    // No es TOML, aunque un fichero sencillo de TOML suele valer: una `clave = valor` por línea,
+    // con comentarios desde un `#` hasta el final de la línea y valores opcionalmente entre
+    // comillas (dentro de las cuales `#` es un carácter más). No hay secciones, ni escapes, ni
+    // valores de varias líneas
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("no se puede leer `{}`: {}", path, e))?;
+        for (n, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("{}:{}: se esperaba `clave = valor`", path, n + 1))?;
+            let value = value.trim();
+            let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+            self.set(key.trim(), value)
+                .map_err(|e| format!("{}:{}: {}", path, n + 1, e))?;
+        }
+        Ok(())
+    }
+}
+
+// La línea sin su comentario: lo que va desde el primer `#` que no está entre comillas
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Un `#` entre comillas es parte del valor, fuera de ellas empieza el comentario
+    #[test]
+    fn comments_outside_quotes() {
+        assert_eq!(strip_comment("seed = 3 # la de siempre"), "seed = 3 ");
+        assert_eq!(strip_comment("sensors = \"a#b\" # c"), "sensors = \"a#b\" ");
+        assert_eq!(strip_comment("# todo comentario"), "");
+        assert_eq!(strip_comment("rounds = 3"), "rounds = 3");
+    }
+
+    // Los parámetros que no tienen sentido con 0, negativos o NaN se rechazan
+    #[test]
+    fn rejects_invalid_values() {
+        let mut config = SimConfig::default();
+        for (key, value) in [
+            ("rounds", "0"),
+            ("rounds", "255"),
+            ("substeps", "0"),
+            ("round_time", "0"),
+            ("sensor_range", "NaN"),
+            ("x_init_pos", "-0.1"),
+            ("border_width", "-1"),
+        ] {
+            assert!(config.set(key, value).is_err(), "{} = {}", key, value);
+        }
+        assert!(config.set("substeps", "1").is_ok());
+    }
+}
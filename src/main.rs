@@ -2,50 +2,54 @@
 // Importes de librerías
 // =======================================================================================================
 
-use core::panic;
+mod batch;
+mod compare;
+mod config;
+mod replay;
+mod rng;
+mod optimize;
+mod robot;
+mod sensors;
+mod ratings;
+mod strategies;
+mod tournament;
+
+pub use batch::{run_batch, Job};
+pub use compare::{compare, Comparison, Tally};
+pub use config::SimConfig;
+pub use optimize::{optimize, Candidate, Method, Optimization, Search};
+pub use ratings::{Rating, Ratings};
+pub use replay::{Recorder, Replay};
+pub use rng::Rng;
+pub use robot::RobotParams;
+pub use sensors::{LineSensor, Sensor, SensorLayout, SensorNoise};
+pub use tournament::{tournament, Ranking, Standing};
+
 use std::{
-    f32::{
-        consts::{PI, SQRT_2},
-        NAN,
-    },
-    ops::{Add, Sub},
-    sync::mpsc::{channel, Receiver},
-    thread::{self, sleep},
-    time::Duration,
+    cmp::Ordering,
+    env,
+    f32::consts::PI,
+    fmt, process,
+    str::FromStr,
+    ops::{Add, Mul, Sub},
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    thread,
 };
 
 use ncurses::{
     addch, attroff, attrset, clear, curs_set, endwin, getch, getmaxx, getmaxy, init_pair, initscr,
-    noecho, refresh, start_color, stdscr, COLOR_BLACK, COLOR_BLUE, COLOR_GREEN, COLOR_PAIR,
-    COLOR_RED,
+    keypad, mvaddstr, noecho, refresh, start_color, stdscr, timeout, COLOR_BLACK, COLOR_BLUE,
+    COLOR_GREEN, COLOR_PAIR, COLOR_RED, ERR, KEY_LEFT, KEY_RIGHT,
 };
 
 // =======================================================================================================
 // Constantes
 // =======================================================================================================
 
-const X_INIT_POS: f32 = 10.0;                         // Coordenada x del estado inicial de los
-                                                      // robots
-
-const SUMO_SIZE: f32 = 2.5;                           // Lado de un robot (son cuadrados)
-
-const DRAWING_BOUNDRY_SIZE: f32 = 0.5;                // Sensibilidad del adaptador gráfico
-
 const ORIGIN: Vec2 = Vec2 { x: 0.0, y: 0.0 };         // Punto 0 en el plano
 
-const REQ_CASTER: SumoState = SumoState {             // Valor que nos permitirá hacer gimnasia de
-                                                      // tipos
-    center: ORIGIN,                                   //
-    dir: 0.0,                                         //
-    corners: [ORIGIN, ORIGIN, ORIGIN, ORIGIN],        //
-};                                                    
-
-const TATAMI_SIZE: f32 = 20.0;                        // Radio del "tatami" (circulo de lucha)
-
-const PUSH_FRICTION: f32 = 1.0;                       // Coefficiente de fricción (no en unidades
-                                                      // del sistema internacional)
-
-const M: f32 = SUMO_SIZE * 4.0 * (SQRT_2 - 1.0) / PI; // aun más gimnasia mental
+const CONTACT_SLOP: f32 = 0.0005;                     // Cuánto dejamos que se metan los robots el
+                                                      // uno en el otro al empujarse (m)
 
 
 // =======================================================================================================
@@ -77,39 +81,55 @@ pub struct Vec2 { //  | Esto es una estructura con dos "campos"
 }                 //  |
 // -------------------| Utilizaremos esta estructura como un vector de 2 dimensiones
 
-// -------------|
-enum Round { // | Esto es una enumeración con 3 "variantes"
-             // | este tipo datos podrá tener una de estas 3
-             // | formas
-    Round1,  // | 
-    Round2,  // |
-    Round3   // |
-}            // |
-//--------------| Utilizaremos esta enumeración para ver en que ronda estamos
-
-// --------------------------------------------|
-impl From<u8> for Round {                   // | Esto es una propiedad no derivable
-                                            // |
-   fn from(value: u8) -> Self { // ----------- | Convierte un tipo "u8" (unsigned 8 bits,
-                                            // | entero sin signo) en una de las variantes de ronda
-                                            // |
-       match value {                        // |-| Esto es el valor que evaluaría llamar `from`
-           1 => Round::Round1,              // | | sobre otro: `1` |-> `Round1`, `2` |-> `Round2`,
-           2 => Round::Round2,              // | | `3` |-> `Round3`, `_` |-> Error
-           3 => Round::Round3,              // | | "_" es un valor que es "igual" a cualquier otro,
-           _ => panic!("bad round number"), // | | esto es útil ya que Rust te fuerza a comprobar
-       }                                    // | | todas las ramas, "_" te permite definir un caso
-                                            // |-| base
-                                            // |
-                                            // | Código escrito en Rust se compone de "expresiones"
-                                            // | (código que evalua a un valor, `x + 1` es una
-                                            // | expresión), las cuales "anulamos" (`x + 1;` es una 
-                                            // | expresión anulada) para que los "bloques" 
-                                            // | (código entre llaves, {`x + 1`} es un bloque) 
-                                            // | solo tenga un valor
-   }                                        // |
-}                                           // |
-// --------------------------------------------|
+// ---------------------------|
+#[derive(Clone, Copy, Debug)] // |
+pub enum Start {              // | Esto es una enumeración con 4 "variantes"
+                              // | este tipo datos podrá tener una de estas 4
+                              // | formas
+    Shikiri,                  // | de frente, cada uno detrás de su línea de salida
+    SideBySide,               // | de lado, mirando en sentidos opuestos
+    BackToBack,               // | de espaldas
+    Random,                   // | en cualquier sitio, mirando a cualquier lado
+}                             // |
+// ----------------------------| Utilizaremos esta enumeración para saber cómo empieza cada ronda
+
+// ------------------------------------------------------|
+impl FromStr for Start {                              // | Esto es una propiedad no derivable
+    type Err = String;                                // | (parecida a `From`, pero que puede
+                                                      // | fallar, y entonces da un texto)
+                                                      // |
+    fn from_str(name: &str) -> Result<Start, String> { // | Convierte un texto (el nombre de la
+                                                      // | configuración) en una de las salidas
+                                                      // |
+        match name.trim().replace('-', "_").as_str() { // |-| Esto es el valor que evaluaría
+            "shikiri" => Ok(Start::Shikiri),          // | | llamar `parse` sobre un texto:
+            "side_by_side" => Ok(Start::SideBySide),  // | | `"shikiri"` |-> `Shikiri`,
+            "back_to_back" => Ok(Start::BackToBack),  // | | `"random"` |-> `Random`... y
+            "random" => Ok(Start::Random),            // | | `_` |-> Error
+            _ => Err(format!("no existe la salida `{}`", name)),
+        }                                             // | | "_" es un valor que es "igual" a
+                                                      // | | cualquier otro, esto es útil ya que
+                                                      // | | Rust te fuerza a comprobar todas las
+                                                      // |-| ramas, "_" te permite definir un
+                                                      // |   caso base
+                                                      // |
+                                                      // | Código escrito en Rust se compone de
+                                                      // | "expresiones" (código que evalua a un
+                                                      // | valor, `x + 1` es una expresión), las
+                                                      // | cuales "anulamos" (`x + 1;` es una
+                                                      // | expresión anulada) para que los
+                                                      // | "bloques" (código entre llaves,
+                                                      // | {`x + 1`} es un bloque) solo tenga un
+                                                      // | valor
+    }                                                 // |
+}                                                     // |
+// ------------------------------------------------------|
+
+// Lee la lista de salidas de la configuración: nombres separados por comas, que se van repitiendo
+// ronda tras ronda
+fn parse_starts(spec: &str) -> Result<Vec<Start>, String> {
+    spec.split(',').map(str::parse).collect()
+}
 
 // ---------------------------------------------------------------|
 impl Vec2 {                                                    // | Estos son propiedades propias de
@@ -121,8 +141,17 @@ impl Vec2 {                                                    // | Estos son pr
         }                                                      // |
     }                                                          // |
                                                                // |
-    fn origin_dir(self) -> f32 { // calcula la dirección       // |
-        f32::atan(self.x / self.y)                             // |
+    fn rotate(self, theta: f32) -> Vec2 { // gira el vector    // |
+                                          // `theta` radianes  // |
+        let (sin, cos) = theta.sin_cos();                      // |
+        Vec2 {                                                 // |
+            x: self.x * cos - self.y * sin,                    // |
+            y: self.x * sin + self.y * cos,                    // |
+        }                                                      // |
+    }                                                          // |
+                                                               // |
+    fn dot(self, rhs: Vec2) -> f32 { // producto escalar       // |
+        self.x * rhs.x + self.y * rhs.y                        // |
     }                                                          // |
                                                                // |
     fn dist(self, sstate: Self) -> f32 { // calcula la         // |
@@ -137,9 +166,57 @@ impl Vec2 {                                                    // | Estos son pr
 type Corners = [Vec2; 4]; // ¡CUIDADO! esto es solo un alias,
                           // otro nombre para `[Vec2; 4]`,
                           // una lista de 4 `Vec2`
+                          //
+                          // Las esquinas van en orden: delantera izquierda,
+                          // delantera derecha, trasera derecha y trasera
+                          // izquierda (recorriendo el perímetro)
 
-type Strategy = fn(f32) -> SumoReq;
-// significa: f(un_valor: f32) -produce-> otro_valor: SumoReq (lo definiermos más adelante) 
+// Lo que "ve" un robot en un paso de la simulación
+#[derive(Clone, Debug)]
+pub struct SensorFrame {
+    pub distance: Vec<f32>, // lectura de cada sensor de distancia, en el orden de la disposición
+                            // del robot (de izquierda a derecha, ver `sensors::layout`)
+    pub line: Vec<bool>,    // si cada sensor de línea está sobre el borde blanco (ver
+                            // `sensors::line_layout`)
+}
+
+// Información temporal que recibe la estrategia junto a sus sensores
+#[derive(Clone, Copy, Debug)]
+pub struct TickInfo {
+    pub round: u8, // ronda en curso (empezando por 1)
+    pub tick: u32, // pasos transcurridos desde el inicio de la ronda
+    pub time: f32, // segundos transcurridos desde el inicio de la ronda
+    pub started: bool, // si ya ha pasado la espera de salida (antes, los motores no obedecen)
+}
+
+// Una estrategia es cualquier cosa que, dado lo que ven los sensores, decida que hacer con los
+// motores. Al recibir `&mut self` puede guardar memoria entre pasos (máquinas de estados,
+// temporizadores, última posición conocida del rival...)
+pub trait SumoStrategy {
+    fn act(&mut self, sensors: &SensorFrame, tick: &TickInfo) -> SumoReq;
+
+    // Se llama al empezar cada ronda; por defecto no hace nada
+    fn reset(&mut self) {}
+
+    // Nombre con el que aparece en las repeticiones; por defecto el nombre del tipo
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// Cualquier función o closure de la forma `f(ir: f32) -> SumoReq` sigue sirviendo como
+// estrategia (sin memoria, solo mira el sensor del medio)
+impl<F> SumoStrategy for F
+where
+    F: FnMut(f32) -> SumoReq,
+{
+    fn act(&mut self, sensors: &SensorFrame, _: &TickInfo) -> SumoReq {
+        self(sensors.distance.get(sensors.distance.len() / 2).copied().unwrap_or(0.0))
+    }
+}
+
+pub type Strategy = Box<dyn SumoStrategy + Send>;
+// una estrategia cualquiera "en caja", para poder mandarla a otro hilo
 
 impl Add<Vec2> for Vec2 { // la propiedad `Add` nos permite usar el operador `+`
 //       ---       ---
@@ -170,81 +247,391 @@ impl Sub<Vec2> for Vec2 { // similarmente, `Sub` nos permite usar el operador `-
     }
 }
 
+impl Mul<f32> for Vec2 { // y `Mul` el operador `*`, escalando el vector
+    type Output = Vec2;
+    fn mul(self, rhs: f32) -> Self::Output {
+        Vec2 {
+            x: self.x * rhs,
+            y: self.y * rhs,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct SumoState {   // esta es la estructura principal del programa,
                          // representa el estado de un robot
     center: Vec2,     // guardamos el valor del centro del robot,
     dir: f32,         // la dirección del robot,
+    size: f32,        // el lado del robot,
+    motors: SumoReq,  // la velocidad a la que giran de verdad sus ruedas,
+    vel: Vec2,        // la velocidad a la que se mueve de verdad (si las ruedas patinan o el
+                      // robot derrapa, no coincide con la de las ruedas),
+    spin: f32,        // a qué velocidad gira (rad/s),
     corners: Corners, // y las esquinas (falicitará el adaptador de graficos)
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct SumoReq { // las estrategias nos darán un valor de este tipo
-    motor_l: f32, // velocidad del motor izquierdo
-    motor_r: f32, // velocidad del motor derecho
+    motor_l: f32, // velocidad de giro de la rueda izquierda (rad/s)
+    motor_r: f32, // velocidad de giro de la rueda derecha (rad/s)
+}
+
+// Por qué se ha terminado una ronda
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoundEnd {
+    PushedOut, // el perdedor ha salido del tatami mientras le empujaban
+    DroveOut,  // el perdedor ha salido del tatami él solito
+    Draw,      // los dos han salido a la vez
+    Timeout,   // se ha acabado el tiempo sin que nadie salga (empate)
+    FalseStart, // el perdedor ha movido los motores antes de tiempo (si lo han hecho los dos,
+                // empate)
+}
+
+// Resultado de una ronda, los robots se identifican por su posición en la lista de estados
+// (0 para la primera estrategia y 1 para la segunda)
+#[derive(Clone, Copy, Debug)]
+pub struct RoundResult {
+    pub round: u8,                    // número de ronda
+    pub winner: Option<usize>,        // ganador, `None` si ha sido empate
+    pub loser: Option<usize>,         // perdedor, `None` si ha sido empate
+    pub cause: RoundEnd,              // cómo ha terminado la ronda
+    pub ticks: u32,                   // pasos que ha durado
+    pub time: f32,                    // segundos que ha durado
+    pub final_states: [SumoState; 2], // estado de los robots al terminar
+}
+
+// Resultado de un combate al mejor de tres rondas
+#[derive(Clone, Debug)]
+pub struct MatchResult {
+    pub rounds: Vec<RoundResult>, // las rondas jugadas, en orden
+    pub wins: [u8; 2],            // rondas ganadas por cada robot
+    pub winner: Option<usize>,    // quién ha ganado más rondas, `None` si empatan
+}
+
+impl MatchResult {
+    // Junta las rondas en un veredicto: gana quien más rondas haya ganado
+    fn from_rounds(rounds: Vec<RoundResult>) -> MatchResult {
+        let mut wins = [0u8; 2];
+        for winner in rounds.iter().filter_map(|r| r.winner) {
+            wins[winner] += 1;
+        }
+        MatchResult {
+            rounds,
+            wins,
+            winner: match wins[0].cmp(&wins[1]) {
+                Ordering::Greater => Some(0),
+                Ordering::Less => Some(1),
+                Ordering::Equal => None,
+            },
+        }
+    }
+
+    // El combate se decide en cuanto alguien gana más de la mitad de las rondas
+    fn is_decided(&self, rounds: u8) -> bool {
+        self.wins.iter().any(|&w| w > rounds / 2)
+    }
+}
+
+// `Display` nos permite escribir los resultados con `println!("{}", ...)`
+impl fmt::Display for RoundResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Ronda {}: ", self.round)?;
+        match (self.winner, self.loser) {
+            (Some(winner), Some(loser)) => write!(
+                f,
+                "gana el robot {} ({} robot {})",
+                winner + 1,
+                match self.cause {
+                    RoundEnd::PushedOut => "empuja fuera al",
+                    RoundEnd::FalseStart => "sale en falso el",
+                    _ => "se sale el",
+                },
+                loser + 1,
+            )?,
+            _ if self.cause == RoundEnd::Timeout => write!(f, "empate por tiempo")?,
+            _ if self.cause == RoundEnd::FalseStart => write!(f, "empate (salen en falso los dos)")?,
+            _ => write!(f, "empate (se salen los dos)")?,
+        }
+        write!(f, " en {:.2} s ({} pasos)", self.time, self.ticks)
+    }
+}
+
+impl fmt::Display for MatchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for round in &self.rounds {
+            writeln!(f, "{}", round)?;
+        }
+        match self.winner {
+            Some(winner) => write!(
+                f,
+                "Gana el robot {} ({} - {})",
+                winner + 1,
+                self.wins[winner],
+                self.wins[1 - winner]
+            ),
+            None => write!(f, "Empate ({} - {})", self.wins[0], self.wins[1]),
+        }
+    }
+}
+
+// Todo lo que ha pasado en un paso de la simulación
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub round: u8,                 // ronda en curso
+    pub tick: u32,                 // paso dentro de la ronda
+    pub states: [SumoState; 2],    // estado de los robots tras el paso
+    pub sensors: [SensorFrame; 2], // lo que vio cada robot antes de decidir
+    pub reqs: [SumoReq; 2],        // lo que pidió cada robot a sus motores
+}
+
+// Lo que el simulador va contando al adaptador gráfico por el canal
+#[derive(Clone, Debug)]
+pub enum SumoEvent {
+    Frame(Frame),           // un nuevo paso de la simulación
+    Round(RoundResult),     // se ha terminado una ronda
+    Match(MatchResult),     // se ha terminado el combate
 }
 
 impl Add<Vec2> for SumoState {
     // la suma representa mover todos los puntos del robot por el vector
     type Output = SumoState;
     fn add(self, rhs: Vec2) -> Self::Output {
+        self.with_pose(rhs + self.center, self.dir)
+    }
+}
+
+impl SumoState {
+    // Construye el estado de un robot a partir de su centro, su dirección y su tamaño, las
+    // esquinas siempre se calculan a partir de estos valores para que el cuadrado gire con el
+    // robot
+    fn new(center: Vec2, dir: f32, size: f32) -> SumoState {
+        let h = size / 2.0;
         SumoState {
-            center: rhs + self.center,
-            dir: self.dir,
-            corners: self.corners.map(|corner| corner + rhs),
-            //                    ^^^
-            // por cada valor de la lista, sumamos el valor con `rhs`
+            center,
+            dir,
+            size,
+            motors: SumoReq {
+                motor_l: 0.0,
+                motor_r: 0.0,
+            },
+            vel: ORIGIN,
+            spin: 0.0,
+            corners: [
+                Vec2 { x: h, y: h },
+                Vec2 { x: h, y: -h },
+                Vec2 { x: -h, y: -h },
+                Vec2 { x: -h, y: h },
+            ]
+            .map(|corner| center + corner.rotate(dir)),
+            //    ^^^
+            // por cada esquina (relativa al centro, mirando hacia `x`), la giramos y la movemos
+            // al centro del robot
         }
     }
+
+    // El mismo robot (mismo tamaño, mismos motores, misma velocidad) en otra posición y dirección
+    fn with_pose(self, center: Vec2, dir: f32) -> SumoState {
+        SumoState {
+            motors: self.motors,
+            vel: self.vel,
+            spin: self.spin,
+            ..SumoState::new(center, dir, self.size)
+        }
+    }
+
+
+    // Los dos ejes del cuadrado (hacia delante y hacia la izquierda del robot), como vectores de
+    // longitud 1
+    fn axes(self) -> [Vec2; 2] {
+        let (sin, cos) = self.dir.sin_cos();
+        [Vec2 { x: cos, y: sin }, Vec2 { x: -sin, y: cos }]
+    }
+
+    // Proyecta las esquinas sobre un eje, devolviendo el intervalo (mínimo, máximo) que ocupa el
+    // robot sobre él
+    fn project(self, axis: Vec2) -> (f32, f32) {
+        self.corners
+            .iter()
+            .map(|corner| corner.dot(axis))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), p| (min.min(p), max.max(p)))
+    }
 }
 
-impl SumoState {
-    // ???
-    // calcula el "radio" del cuadrado, 
-    // teniendo en cuenta la dirección relativa del robot
-    // ???
-    fn radius_towards(self, theta: f32) -> f32 {
-        let rel_dir = (theta - self.dir) % (PI / 2.0); // definimos una variable temporal
-                                                       // el acceso de cualquier modo a
-                                                       // esta variable fuera de su "mira"
-                                                       // resultará en error
-        if rel_dir <= PI / 4.0 {
-            M * rel_dir + SUMO_SIZE // asumo que esta relación es linear
+// Información de un choque entre dos robots
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    normal: Vec2, // dirección del choque, de longitud 1 y apuntando del primer robot al segundo
+    depth: f32,   // cuánto se están "metiendo" el uno en el otro a lo largo de `normal`
+}
+
+// Detecta si dos robots se tocan usando el teorema del eje separador: dos rectángulos no se tocan
+// si y solo si existe un eje (basta con probar las normales de sus lados) sobre el cual sus
+// proyecciones no se solapan. Si se solapan en todos, el eje con menor solape nos da la normal
+// del choque y ese solape es la profundidad
+fn collide(a: SumoState, b: SumoState) -> Option<Contact> {
+    let mut best: Option<Contact> = None;
+    for axis in a.axes().into_iter().chain(b.axes()) {
+        let (amin, amax) = a.project(axis);
+        let (bmin, bmax) = b.project(axis);
+        let overlap = amax.min(bmax) - amin.max(bmin);
+        if overlap <= 0.0 {
+            return None; // hemos encontrado un eje separador, no hay choque
+        }
+        if best.is_none_or(|c| overlap < c.depth) {
+            best = Some(Contact {
+                normal: axis,
+                depth: overlap,
+            });
+        }
+    }
+    best.map(|c| Contact {
+        // la normal debe apuntar de `a` hacia `b`
+        normal: if (b.center - a.center).dot(c.normal) < 0.0 {
+            c.normal * -1.0
         } else {
-            M * (PI / 2.0 - rel_dir) + SUMO_SIZE
+            c.normal
+        },
+        depth: c.depth,
+    })
+}
+
+// Velocidad común (a lo largo del choque) de dos robots que se empujan, tras `dt` segundos
+//
+// Partimos de la velocidad `v0` a la que iban juntos (con `mass` la masa de los dos) y cada robot
+// da, como `(velocidad, fuerza)`, la velocidad a la que le llevan sus ruedas en esa dirección y la
+// fuerza máxima que pueden hacer contra el suelo. Las ruedas empujan con toda su fuerza hacia su
+// velocidad, salvo que ya vayan a ella: entonces hacen justo la fuerza que haga falta para
+// mantenerla (rozamiento estático), y por eso un robot frenado aguanta a uno que empuja con la
+// misma fuerza
+fn shared_speed(v0: f32, mass: f32, dt: f32, wheels: [(f32, f32); 2]) -> f32 {
+    // a dónde llegan si, a velocidad `v`, cada rueda empuja con toda su fuerza (las que ya van a
+    // `v` empujan como diga `tie`, de -1 a 1)
+    let reach = |v: f32, tie: f32| {
+        let push: f32 = wheels
+            .iter()
+            .map(|&(w, force)| match w.total_cmp(&v) {
+                Ordering::Greater => force,
+                Ordering::Less => -force,
+                Ordering::Equal => tie * force,
+            })
+            .sum();
+        v0 + push / mass * dt
+    };
+    let mut speeds = wheels.map(|(w, _)| w);
+    speeds.sort_by(f32::total_cmp);
+    // o bien acaban a la velocidad de alguna de las ruedas ...
+    for v in speeds {
+        if reach(v, -1.0) <= v && v <= reach(v, 1.0) {
+            return v;
         }
     }
-    // `rel_dir` es inaccesible aquí
+    // ... o bien entre ellas, con todas las ruedas empujando a tope
+    [
+        (f32::NEG_INFINITY, speeds[0]),
+        (speeds[0], speeds[1]),
+        (speeds[1], f32::INFINITY),
+    ]
+    .into_iter()
+    .map(|(min, max)| {
+        // cualquier velocidad dentro del tramo sirve, todas dan lo mismo (en los tramos sin
+        // final, la del infinito: en el borde alguna rueda ya no empujaría)
+        let inside = match (min.is_finite(), max.is_finite()) {
+            (true, true) => (min + max) / 2.0,
+            (false, _) => min,
+            (_, false) => max,
+        };
+        (min, reach(inside, 0.0), max)
+    })
+    .find(|&(min, v, max)| min < v && v < max)
+    .map_or(v0, |(_, v, _)| v)
 }
 
 impl SumoReq {
-    fn vel(self) -> f32 {
-        (self.motor_l + self.motor_r) / 2.0 // implementación formula de la 
-                                            // velocidad del accionamiento
-                                            // differencial
+    fn vel(self, robot: &RobotParams) -> f32 {
+        (self.motor_l + self.motor_r) / 2.0 * robot.wheel_radius // implementación formula de la
+                                                                 // velocidad del accionamiento
+                                                                 // differencial
+    }
+
+    // Igual con la velocidad de giro del robot (rad/s) para un robot de lado `size`: la diferencia
+    // entre lo que avanza cada rueda repartida entre la distancia que las separa
+    fn spin(self, robot: &RobotParams, size: f32) -> f32 {
+        (self.motor_r - self.motor_l) * robot.wheel_radius / robot.base(size)
     }
 }
 
-impl Add<SumoReq> for SumoState {
-    type Output = SumoState;
-    fn add(self, sstate: SumoReq) -> SumoState {
-        let delta_teta =
-            (((sstate.motor_r - sstate.motor_l) / SUMO_SIZE) + self.dir) % (2.0 * PI);
-            // usamos la formula del ángulo resultante en el accionamiento differencial ...
-        let vel = sstate.vel();
-            // además de calcular la velocidad ...
-
-        let dv = Vec2 {
-            x: delta_teta.cos() * vel,
-            y: delta_teta.sin() * vel,
-        };  // para calcular el vector de "fuerza" (desconozco si este es el termino)
+impl SumoState {
+    // Hace girar las ruedas del robot a esas velocidades (en rad/s) durante `dt` segundos y calcula
+    // la velocidad que coge el robot (ojo: velocidades reales de las ruedas, lo que pide la
+    // estrategia pasa antes por `RobotParams::motor_step`)
+    //
+    // Las ruedas no se agarran al tatami de forma perfecta: empujan al robot hacia la velocidad
+    // que marcan, pero como mucho con la fuerza que aguanta el rozamiento (si hace falta más,
+    // patinan), y frenan lo que el robot se mueva de lado, también hasta un límite (si le empujan
+    // de lado con más fuerza, derrapa)
+    fn accelerate(self, sstate: SumoReq, robot: &RobotParams, dt: f32) -> SumoState {
+        let [forward, left] = self.axes();
+        let (along, side) = (self.vel.dot(forward), self.vel.dot(left));
+            // separamos la velocidad del robot en la parte que llevan las ruedas y la de lado ...
+
+        let max_dv = robot.push_force() / robot.mass * dt;
+        let along = along + (sstate.vel(robot) - along).clamp(-max_dv, max_dv);
+            // las ruedas llevan al robot hacia la velocidad del accionamiento differencial ...
+        let max_side = robot.grip_force() / robot.mass * dt;
+        let side = side - side.clamp(-max_side, max_side);
+            // ... y frenan el movimiento de lado
+
+        let max_dw =
+            3.0 * robot.push_force() * robot.base(self.size) / (robot.mass * self.size.powi(2)) * dt;
+            // lo mismo con el giro: las ruedas, a media distancia entre ruedas del centro, hacen
+            // girar un cuadrado de inercia `mass * size² / 6` ...
+        let spin = self.spin + (sstate.spin(robot, self.size) - self.spin).clamp(-max_dw, max_dw);
+            // ... hacia el giro que marca la formula del accionamiento differencial
 
         SumoState {
-            center: self.center + dv,
-            dir: delta_teta,
-            corners: self.corners.map(|corner| corner + dv),
+            motors: sstate,
+            vel: forward * along + left * side,
+            spin,
+            ..self
         }
     }
+
+    // Mueve el robot a su velocidad durante `dt` segundos
+    //
+    // Mientras gira, el robot no avanza en línea recta sino que recorre un arco de circunferencia
+    // (la velocidad gira con él), así que lo integramos de forma exacta: el desplazamiento es la
+    // velocidad por `dt`, girada y acortada según lo que gire el robot durante ese tiempo. Girando
+    // sobre sí mismo no se mueve del sitio, y el radio de giro sale justo `vel / spin`
+    fn advance(self, dt: f32) -> SumoState {
+        let turn = self.spin * dt;
+        let (ahead, aside) = if turn.abs() > 1e-6 {
+            (turn.sin() / turn, (1.0 - turn.cos()) / turn)
+        } else {
+            (1.0, turn / 2.0) // sin apenas giro, casi en línea recta (y sin dividir entre 0)
+        };
+        let perp = Vec2 {
+            x: -self.vel.y,
+            y: self.vel.x,
+        }; // la velocidad girada 90º a la izquierda
+        SumoState {
+            vel: self.vel.rotate(turn),
+            ..self.with_pose(
+                self.center + (self.vel * ahead + perp * aside) * dt,
+                (self.dir + turn) % (2.0 * PI),
+            )
+        }
+    }
+
+    // Hacia dónde le llevan al robot sus ruedas a lo largo de `axis` y con cuánta fuerza pueden
+    // empujar (o aguantar) en esa dirección, que depende de si es hacia delante o de lado
+    fn wheels_along(self, robot: &RobotParams, axis: Vec2) -> (f32, f32) {
+        let [forward, left] = self.axes();
+        let (along, side) = (forward.dot(axis), left.dot(axis));
+        (
+            self.motors.vel(robot) * along,
+            robot.push_force() * along.abs() + robot.grip_force() * side.abs(),
+        )
+    }
 }
 
 fn is_near<T>(x: T, y: T, bound: T) -> bool
@@ -262,76 +649,71 @@ where
 // aplicar los vectores de fuerza resultantes, y calcular la respuesta de los robots
 // dado la respuesta de los sensores infrarojos que tambien calcula esta función
 //
-pub fn probe_strategy(strat1: Strategy, strat2: Strategy) -> Receiver<[SumoState; 2]> {
+// Cada cosa que pasa se la cuenta a `on_event` (para dibujarla, guardarla o ignorarla) y al final
+// devuelve el resultado del combate
+fn run_match(
+    strat1: &mut dyn SumoStrategy,
+    strat2: &mut dyn SumoStrategy,
+    config: &SimConfig,
+    mut on_event: impl FnMut(SumoEvent),
+) -> MatchResult {
     // Para mantener mi sanidad, he roto el problema en trozos manejables y modulares:
     
-    // Esta función dado un número de ronda devuelve el estado inicial en el que deberían de estar
-    // los robots
-    // Su longitud se debe al estilizado de código
-    fn round_start(round: Round) -> [SumoState; 2] {[
-        SumoState {
-            center: Vec2 {
-                x: X_INIT_POS,
-                y: 0.0,
-            },
-            dir: match round {
-                Round::Round1 => PI,
-                Round::Round2 => PI/2.0,
-                Round::Round3 => 0.0,
-            },
-            corners: [
-                Vec2 {
-                    x: X_INIT_POS + SUMO_SIZE / 2.0,
-                    y: SUMO_SIZE / 2.0,
-                },
-                Vec2 {
-                    x: X_INIT_POS + SUMO_SIZE / 2.0,
-                    y: -SUMO_SIZE / 2.0,
-                },
-                Vec2 {
-                    x: X_INIT_POS - SUMO_SIZE / 2.0,
-                    y: SUMO_SIZE / 2.0,
-                },
-                Vec2 {
-                    x: X_INIT_POS - SUMO_SIZE / 2.0,
-                    y: -SUMO_SIZE / 2.0,
-                },
-            ],
-        },
-        SumoState {
-            center: Vec2 {
-                x: -X_INIT_POS,
-                y: 0.0,
-            },
-            dir: match round {
-                Round::Round1 => 0.0,
-                Round::Round2 => 3.0*PI/2.0,
-                Round::Round3 => PI,
-            },
-
-            corners: [
-                Vec2 {
-                    x: (-X_INIT_POS) + SUMO_SIZE / 2.0,
-                    y: SUMO_SIZE / 2.0,
-                },
-                Vec2 {
-                    x: (-X_INIT_POS) + SUMO_SIZE / 2.0,
-                    y: -SUMO_SIZE / 2.0,
-                },
-                Vec2 {
-                    x: (-X_INIT_POS) - SUMO_SIZE / 2.0,
-                    y: SUMO_SIZE / 2.0,
-                },
-                Vec2 {
-                    x: (-X_INIT_POS) - SUMO_SIZE / 2.0,
-                    y: -SUMO_SIZE / 2.0,
-                },
-            ],
-        },
-    ]}
+    // Esta función dada una salida devuelve el estado inicial en el que deberían de estar los
+    // robots (las salidas aleatorias sacan sus números de `rng`)
+    fn round_start(start: Start, config: &SimConfig, rng: &mut Rng) -> [SumoState; 2] {
+        let robot = |x: f32, y: f32, dir: f32| SumoState::new(Vec2 { x, y }, dir, config.sumo_size);
+        let x = config.x_init_pos;
+        let spots = match start {
+            Start::Shikiri => [(x, 0.0, PI), (-x, 0.0, 0.0)],
+            Start::SideBySide => [(x, 0.0, PI / 2.0), (-x, 0.0, 3.0 * PI / 2.0)],
+            Start::BackToBack => [(x, 0.0, 0.0), (-x, 0.0, PI)],
+            Start::Random => {
+                // el centro de cada robot, en cualquier punto desde el que no se salga ninguna
+                // esquina ...
+                let reach = (config.tatami_size - config.sumo_size).max(0.0);
+                let mut spot = || loop {
+                    let p = Vec2 {
+                        x: rng.range(-reach, reach),
+                        y: rng.range(-reach, reach),
+                    };
+                    if p.dist(ORIGIN) <= reach {
+                        break p;
+                    }
+                };
+                // ... y sin que los robots se toquen (aunque empiecen cerca)
+                let gap = (2.0 * config.sumo_size).min(reach);
+                let (a, b) = loop {
+                    let (a, b) = (spot(), spot());
+                    if a.dist(b) >= gap {
+                        break (a, b);
+                    }
+                };
+                return [
+                    robot(a.x, a.y, rng.range(0.0, 2.0 * PI)),
+                    robot(b.x, b.y, rng.range(0.0, 2.0 * PI)),
+                ];
+            }
+        };
+        // en las salidas fijas, cada robot se coloca un poco desplazado y torcido si así lo piden
+        // `start_jitter` y `heading_jitter` (como haría una persona poniéndolo a mano)
+        spots.map(|(x, y, dir)| {
+            if config.start_jitter <= 0.0 && config.heading_jitter <= 0.0 {
+                return robot(x, y, dir);
+            }
+            let (angle, r) = (rng.range(0.0, 2.0 * PI), config.start_jitter * rng.uniform().sqrt());
+            robot(
+                x + r * angle.cos(),
+                y + r * angle.sin(),
+                dir + rng.range(-config.heading_jitter, config.heading_jitter),
+            )
+        })
+    }
 
     // Siguiendo la analogía del motor, esta función sería el bloque motor del motor, es la
     // encargada de toda la lógica
+    //
+    // Además de los nuevos estados, nos dice si los robots se han tocado durante el paso
     fn update(
         [sysl, sysr]: [SumoState; 2],
 //       -----------
@@ -339,103 +721,273 @@ pub fn probe_strategy(strat1: Strategy, strat2: Strategy) -> Receiver<[SumoState
 //  manera
 //       __________
         [reql, reqr]: [SumoReq; 2],
-    ) -> Option<[SumoState; 2]> {
-        let mut sy_s = [sysl + reql, sysr + reqr]; // actualizamos la lista de dos entradas con los
-                                                   // estados previos para sumarle las respuesta de
-                                                   // sus correspondientes motores
-        let theta = (sy_s[0].center - sy_s[1].center).origin_dir(); // calculamos el ángulo entre
-                                                                    // el centro de los dos robots
-
-        if sysl.center.dist(sysr.center) // | por lo que la comparamos con la distancia entre real
-                                         // | entre estos dos
-                                         // |___
-            < sy_s[0].radius_towards(theta) // ----| La suma de los "radios" en el ángulo calculado
-                + sy_s[1].radius_towards(theta) // | previamente resulta en el menor valor en el
-                                                // | que los dos robots no se están tocando ...
-        {
-            let vatt = reqr.vel() - reql.vel();
-            let ap = if vatt > 0.0 {
-                |x, y| x + y
-            } else {
-                |x, y| x + (ORIGIN - y)
-            };
-            let (gyatt, xatt) = (REQ_CASTER + reqr).dir.sin_cos();
-            let vec_push = Vec2 {
-                x: xatt * vatt / PUSH_FRICTION,
-                y: gyatt * vatt / PUSH_FRICTION,
-            };
-            sy_s = [ap(sysl, vec_push), ap(sysr, ORIGIN - vec_push)];
-        }; // Sinceramente, no sé que hice aquí, tendría que redescrubrir en lo que he pensado para
-           // explicarlo
-           //
-           // Basicamente aplica el vector fuerza si existe colisión
+        config: &SimConfig,
+    ) -> ([SumoState; 2], bool) {
+        // Cada paso lo dividimos en `substeps` pasitos más cortos para que los choques sean
+        // estables (a pasos largos, los robots se meten mucho el uno en el otro antes de notarlo)
+        let dt = config.dt / config.substeps as f32;
+        let masses = config.robots.clone().map(|r| r.mass);
+        let mut sy_s = [sysl, sysr];
+        let mut touching = false;
+        for _ in 0..config.substeps {
+            let prev = sy_s;
+            // los motores responden a lo que piden las estrategias como pueden y las ruedas
+            // empujan a los robots ...
+            sy_s = [0, 1].map(|i| {
+                let req = [reql, reqr][i];
+                let robot = &config.robots[i];
+                prev[i].accelerate(robot.motor_step(prev[i].motors, req, dt), robot, dt)
+            });
 
-        if sysl.center.dist(ORIGIN) < TATAMI_SIZE && sysr.center.dist(ORIGIN) < TATAMI_SIZE {
-            // Comprobamos si los dos robots siguen en el tatami
+            if let Some(Contact { normal, .. }) = collide(prev[0], prev[1]) {
+                // ... pero si se tocan, miramos a qué velocidad va cada uno en la dirección del
+                // choque ...
+                let (ul, ur) = (sy_s[0].vel.dot(normal), sy_s[1].vel.dot(normal));
+                if ul > ur {
+                    // ... y, si se están acercando, los dos pasan a moverse juntos en esa
+                    // dirección: a la velocidad a la que iban (conservando el momento, pesa más
+                    // la del más pesado) más lo que consigan las ruedas de cada uno. Acaba
+                    // ganando quien más fuerza haga contra el suelo, y el que pierde patina (si le
+                    // empujan de frente) o derrapa (si le empujan de lado)
+                    let v0 = (masses[0] * prev[0].vel.dot(normal)
+                        + masses[1] * prev[1].vel.dot(normal))
+                        / (masses[0] + masses[1]);
+                    let wheels = [
+                        sy_s[0].wheels_along(&config.robots[0], normal),
+                        sy_s[1].wheels_along(&config.robots[1], normal),
+                    ];
+                    let v = shared_speed(v0, masses[0] + masses[1], dt, wheels);
+                    sy_s[0].vel = sy_s[0].vel + normal * (v - ul);
+                    sy_s[1].vel = sy_s[1].vel + normal * (v - ur);
+                }
+                touching = true;
+            }
+            // ... y actualizamos la lista de dos entradas moviendo los robots
+            sy_s = sy_s.map(|state| state.advance(dt));
 
-            Some(sy_s) // Si esto es el caso, la partida sigue, tenemos "algún" estado
-        //  ---- de ahí este constructor
-        } else {
-            None // Si no, la partida se ha acabado, no tenemos estado
-        //  ---- de ahí este constructor
+            // Por último, si se han metido el uno en el otro los separamos, moviendo más al que
+            // menos pesa (pero no del todo, así en el siguiente pasito siguen tocándose y sus
+            // ruedas siguen empujándose)
+            if let Some(Contact { normal, depth }) = collide(sy_s[0], sy_s[1]) {
+                let depth = (depth - CONTACT_SLOP).max(0.0);
+                let total = masses[0] + masses[1];
+                sy_s = [
+                    sy_s[0] + normal * (-depth * masses[1] / total),
+                    sy_s[1] + normal * (depth * masses[0] / total),
+                ];
+                touching = true;
+            }
         }
-        // Nótese que no podríamos simplemente devolver `sy_s` y un valor nulo, Rust no tiene
-        // valores nulos ya que son un peligro para la seguridad y las mentes de los programadores
+
+        (sy_s, touching)
     }
 
-    // Calcula la señal del sensor infrarojos (realmente es ultrasonidos pero queda más feo)
-    //
-    // Este valor no es exactamente el valor del sensor, si no la distancia del centro entre los
-    // dos robots (si llegan a verse, si no, devuelve 0)
-    //
-    // !!!
-    // Si conoce aluna formula relativamente sencilla de calcular este valor exacto, por favor
-    // comentemelo
-    // !!!
-    fn calc_ir(s1: SumoState, s2: SumoState, dist: f32) -> f32 {
-        let (min_corner, max_corner) = s2
-            .corners
-            .iter()
-            .map(|v| v.origin_dir())
-            .fold((0.0f32, 0.0f32), |(x, y), t| (x.min(y), y.max(t)));
-        if s1.dir <= max_corner && s1.dir >= min_corner {
-            dist
-        } else {
-            0.0
-        }
+    // El árbitro: mira si alguno de los robots se ha salido del tatami y, si es así, decide quién
+    // ha ganado la ronda
+    fn judge(
+        round: u8,
+        tick: u32,
+        states: [SumoState; 2],
+        touching: bool,
+        config: &SimConfig,
+    ) -> Option<RoundResult> {
+        let out = states.map(|s| s.center.dist(ORIGIN) >= config.tatami_size);
+        let cause = if touching { RoundEnd::PushedOut } else { RoundEnd::DroveOut };
+        let (winner, loser, cause) = match out {
+            [false, false] => return None, // Si los dos siguen en el tatami, la ronda sigue, no
+                                           // tenemos resultado
+            [true, true] => (None, None, RoundEnd::Draw),
+            [true, false] => (Some(1), Some(0), cause),
+            [false, true] => (Some(0), Some(1), cause),
+        };
+        Some(RoundResult { // Si no, la ronda se ha acabado, tenemos "algún" resultado
+    //  ---- de ahí este constructor
+            round,
+            winner,
+            loser,
+            cause,
+            ticks: tick + 1,
+            time: (tick + 1) as f32 * config.dt,
+            final_states: states,
+        })
+        // Nótese que no podríamos simplemente devolver el resultado o un valor nulo, Rust no tiene
+        // valores nulos ya que son un peligro para la seguridad y las mentes de los programadores
     }
 
     // Finalmente, esta es la lógica principal
-    //
-    // No importa mucho los detalles de la implementación
-    // Básicamente crea un canal de comunicación entre "hilos" (tareas que su ordenador ejecuta de
-    // manera simultánea) para que los valores calculados puedan ser representados por el adaptador
-    // gráfico de manera inmediata
-    let (s_tx, states) = channel();
-    thread::spawn(move || {
-    for round in 1u8..=3u8 {
-        let mut sym_state = round_start(Round::from(round));
-        loop {
-            let dist = sym_state[0].center.dist(sym_state[1].center);
+    // (las disposiciones de sensores ya se comprobaron al configurarlas en `SimConfig::set`)
+    let layouts = [0, 1].map(|robot| SensorLayout::for_robot(robot, config).unwrap());
+    let rng = Rng::new(config.seed);
+    let mut noise = [0, 1].map(|robot| SensorNoise::new(rng.fork(robot)));
+    let start_rng = rng.fork(2);
+    let starts = parse_starts(&config.starts).unwrap(); // también se comprobaron
+    let mut rounds = Vec::new();
+    for round in 1.. {
+        // las salidas se van repitiendo en orden (la prórroga empieza como la siguiente que
+        // toque), y cada ronda tiene su propio generador para que las aleatorias no dependan de
+        // lo que haya pasado antes
+        let start = starts[(round as usize - 1) % starts.len()];
+        let mut sym_state = round_start(start, config, &mut start_rng.fork(round as u64));
+        strat1.reset();
+        strat2.reset();
+        noise.iter_mut().for_each(SensorNoise::reset);
+        let mut result = None;
+        // la ronda empieza con la espera de salida: la simulación corre (los sensores leen y las
+        // estrategias deciden) pero los motores no hacen caso hasta que pasa
+        let delay_ticks = (config.start_delay / config.dt).round() as u32;
+        let max_ticks = delay_ticks.saturating_add((config.round_time / config.dt).round() as u32);
+        for tick in 0..max_ticks {
+            let info = TickInfo {
+                round,
+                tick,
+                time: tick as f32 * config.dt,
+                started: tick >= delay_ticks,
+            };
+            let sensors = [
+                noise[0].apply(layouts[0].sense(sym_state[0], sym_state[1], config), config),
+                noise[1].apply(layouts[1].sense(sym_state[1], sym_state[0], config), config),
+            ];
             let ir_reads: [SumoReq; 2] = [
-                strat1(calc_ir(sym_state[0], sym_state[1], dist)),
-                strat2(calc_ir(sym_state[1], sym_state[0], dist)),
+                strat1.act(&sensors[0], &info),
+                strat2.act(&sensors[1], &info),
             ];
-            match update(sym_state, ir_reads) {
-                Some(symst) => sym_state = symst,
-                None => break,
+            let motors = if info.started {
+                ir_reads
+            } else {
+                [SumoReq {
+                    motor_l: 0.0,
+                    motor_r: 0.0,
+                }; 2]
             };
-            s_tx.send(sym_state).unwrap();
+            let (symst, touching) = update(sym_state, motors, config);
+            sym_state = symst;
+            on_event(SumoEvent::Frame(Frame {
+                round,
+                tick,
+                states: sym_state,
+                sensors,
+                reqs: ir_reads,
+            }));
+            result = judge(round, tick, sym_state, touching, config);
+            if !info.started && config.false_start {
+                // si alguien intenta moverse antes de tiempo, pierde la ronda
+                let moved = [0, 1].map(|i| {
+                    let deadband = config.robots[i].deadband;
+                    ir_reads[i].motor_l.abs() > deadband || ir_reads[i].motor_r.abs() > deadband
+                });
+                let (winner, loser) = match moved {
+                    [true, false] => (Some(1), Some(0)),
+                    [false, true] => (Some(0), Some(1)),
+                    _ => (None, None),
+                };
+                if moved.contains(&true) {
+                    result = Some(RoundResult {
+                        round,
+                        winner,
+                        loser,
+                        cause: RoundEnd::FalseStart,
+                        ticks: tick + 1,
+                        time: (tick + 1) as f32 * config.dt,
+                        final_states: sym_state,
+                    });
+                }
+            }
+            if result.is_some() {
+                break;
+            }
+        }
+        // si nadie ha salido a tiempo, es empate
+        let result = result.unwrap_or(RoundResult {
+            round,
+            winner: None,
+            loser: None,
+            cause: RoundEnd::Timeout,
+            ticks: max_ticks,
+            time: max_ticks as f32 * config.dt,
+            final_states: sym_state,
+        });
+        on_event(SumoEvent::Round(result));
+        rounds.push(result);
+
+        let verdict = MatchResult::from_rounds(rounds.clone());
+        if verdict.is_decided(config.rounds)
+            || round > config.rounds // ya hemos jugado la prórroga
+            || round == config.rounds && (verdict.winner.is_some() || !config.extension)
+        {
+            break;
         }
-    }});
+    }
+    let result = MatchResult::from_rounds(rounds);
+    on_event(SumoEvent::Match(result.clone()));
+    result
+}
+
+// Simula el combate en otro hilo y devuelve el extremo receptor del canal por el que nos irá
+// contando lo que pasa. Si le damos un `Recorder`, además lo guarda todo en una repetición
+//
+// No importa mucho los detalles de la implementación
+// Básicamente crea un canal de comunicación entre "hilos" (tareas que su ordenador ejecuta de
+// manera simultánea) para que los valores calculados puedan ser representados por el adaptador
+// gráfico de manera inmediata
+pub fn probe_strategy(
+    mut strat1: Strategy,
+    mut strat2: Strategy,
+    config: SimConfig,
+    mut recorder: Option<Recorder>,
+) -> Receiver<SumoEvent> {
+    let (s_tx, states) = channel();
+    thread::spawn(move || {
+        run_match(&mut *strat1, &mut *strat2, &config, |event| {
+            record_event(&mut recorder, &event);
+            // si nadie escucha ya, da igual que se pierda
+            let _ = s_tx.send(event);
+        })
+    });
     states
 }
 
+// Guarda `event` en la repetición, si la hay. Si no se puede escribir, lo avisamos y dejamos de
+// grabar, pero la simulación sigue
+fn record_event(recorder: &mut Option<Recorder>, event: &SumoEvent) {
+    if let Some(Err(e)) = recorder.as_mut().map(|r| r.record(event)) {
+        eprintln!("no se puede seguir grabando la repetición: {}", e);
+        *recorder = None;
+    }
+}
+
+// Simula el combate sin hilos ni gráficos, tan rápido como pueda el ordenador, y devuelve
+// directamente el resultado
+pub fn simulate_match(
+    mut strat1: Strategy,
+    mut strat2: Strategy,
+    config: &SimConfig,
+) -> MatchResult {
+    run_match(&mut *strat1, &mut *strat2, config, |_| ())
+}
+
 // Este es el adaptador gráfico
 // No importa mucho los detalles de su implementación y me avergüenza haber escrito código tan feo
-pub fn graphics_driver(states: Receiver<[SumoState; 2]>) {
+//
+// Guarda todos los pasos que le llegan para poder moverse por ellos con el teclado:
+//
+// | tecla       | acción                                 |
+// |-------------|----------------------------------------|
+// | espacio     | pausar / seguir                        |
+// | `→` o `.`   | avanzar un paso (y pausar)             |
+// | `←` o `,`   | retroceder un paso (y pausar)          |
+// | `+` / `-`   | más / menos velocidad                  |
+// | `n`         | saltar a la siguiente ronda            |
+// | `r`         | volver a empezar                       |
+// | `q`         | salir                                  |
+//
+// Devuelve el resultado del combate si llega a recibirlo
+pub fn graphics_driver(states: Receiver<SumoEvent>, config: &SimConfig) -> Option<MatchResult> {
     let maxx = getmaxx(stdscr());
     let maxy = getmaxy(stdscr());
+    let scale = config.tatami_size / 20.0; // unidades por casilla, el tatami siempre se dibuja
+                                           // con un radio de 20 casillas
+    let to_cells = |v: Vec2| (v * (1.0 / scale)).round();
+    let boundary = config.drawing_boundary_size;
 
     fn as_linear(x: f32, s: SumoState) -> f32 {
         let dom_res = if s.dir > PI / 2.0 && s.dir < PI * 1.5 {
@@ -444,48 +996,73 @@ pub fn graphics_driver(states: Receiver<[SumoState; 2]>) {
             |x, y| x > y
         };
         if dom_res(s.center.x, x) {
-            return NAN;
+            return f32::NAN;
         }
         let m = s.dir.tan();
         x * m + (s.center.y - s.center.x * m)
     }
-    if states.try_recv().is_err() {
-        sleep(Duration::from_millis(50))
-    }
-    while let Ok(frame) = states.try_recv() {
+    keypad(stdscr(), true); // para poder leer las flechas
+    let mut frames: Vec<Frame> = Vec::new(); // todos los pasos recibidos hasta ahora
+    let mut rounds: Vec<RoundResult> = Vec::new();
+    let mut result = None;
+    let mut finished = false; // si la simulación ha cerrado el canal
+    let mut pos = 0; // paso que estamos viendo
+    let mut paused = false;
+    let mut delay = ((config.dt * 1000.0) as i32).clamp(1, 2000); // milisegundos entre paso y
+                                                                  // paso (de primeras, a tiempo
+                                                                  // real, dentro de lo que dejan
+                                                                  // `+` y `-`)
+    loop {
+        // recogemos todo lo que haya llegado desde la última vez
+        loop {
+            match states.try_recv() {
+                Ok(SumoEvent::Frame(frame)) => frames.push(frame),
+                Ok(SumoEvent::Round(res)) => rounds.push(res),
+                Ok(SumoEvent::Match(res)) => result = Some(res),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    finished = true; // la simulación ya no va a mandar nada más
+                    break;
+                }
+            }
+        }
+        let Some(&Frame { round, tick, states: frame, .. }) = frames.get(pos) else {
+            if result.is_some() || finished {
+                break; // el combate ha terminado sin ningún paso que enseñar
+            }
+            // mientras llega el primer paso también se puede salir
+            timeout(delay);
+            if getch() == 'q' as i32 {
+                break;
+            }
+            continue;
+        };
         clear();
-        for y in 0..maxy {
+        for y in 0..maxy - 1 { // la última fila es para la barra de estado
             for x in 0..maxx - 1 {
                 let cx = (x / 2 - maxx / 4) as f32;
                 let cy = (y - maxy / 2) as f32;
-                let d = f32::sqrt((cx * cx) as f32 + (cy * cy) as f32);
-                if frame[0].center.x.round() == cx && frame[0].center.y.round() == cy {
+                let d = f32::sqrt(cx * cx + cy * cy);
+                let cell = Vec2 { x: cx, y: cy };
+                if to_cells(frame[0].center) == cell {
                     attrset(COLOR_PAIR(1));
                     addch('=' as u32);
-                } else if frame[1].center.x.round() == cx && frame[1].center.y.round() == cy {
+                } else if to_cells(frame[1].center) == cell {
                     attrset(COLOR_PAIR(2));
                     addch('=' as u32);
-                } else if frame[0]
-                    .corners
-                    .map(|corner| corner.round())
-                    .contains(&Vec2 { x: cx, y: cy })
-                {
+                } else if frame[0].corners.map(to_cells).contains(&cell) {
                     attrset(COLOR_PAIR(1));
                     addch('o' as u32);
-                } else if frame[1]
-                    .corners
-                    .map(|corner| corner.round())
-                    .contains(&Vec2 { x: cx, y: cy })
-                {
+                } else if frame[1].corners.map(to_cells).contains(&cell) {
                     attrset(COLOR_PAIR(2));
                     addch('o' as u32);
-                } else if is_near(d, TATAMI_SIZE as f32, DRAWING_BOUNDRY_SIZE) {
+                } else if is_near(d, config.tatami_size / scale, boundary) {
                     attrset(COLOR_PAIR(0));
                     addch('#' as u32);
-                } else if is_near(as_linear(cx, frame[0]), cy, DRAWING_BOUNDRY_SIZE) {
+                } else if is_near(as_linear(cx * scale, frame[0]) / scale, cy, boundary) {
                     attrset(COLOR_PAIR(1));
                     addch('.' as u32);
-                } else if is_near(as_linear(cx, frame[1]), cy, DRAWING_BOUNDRY_SIZE) {
+                } else if is_near(as_linear(cx * scale, frame[1]) / scale, cy, boundary) {
                     attrset(COLOR_PAIR(2));
                     addch('.' as u32);
                 } else {
@@ -497,13 +1074,78 @@ pub fn graphics_driver(states: Receiver<[SumoState; 2]>) {
             }
             addch('\n' as u32);
         }
+
+        // barra de estado: dónde estamos y, si la ronda ha terminado, cómo
+        attrset(COLOR_PAIR(0));
+        let status = match rounds.iter().find(|r| r.round == round && r.ticks == tick + 1) {
+            Some(res) => res.to_string(),
+            None => {
+                let waiting = tick < (config.start_delay / config.dt).round() as u32;
+                format!(
+                    "Ronda {}, {:.2} s (paso {}){}",
+                    round,
+                    tick as f32 * config.dt,
+                    tick,
+                    if waiting { ", esperando la salida" } else { "" }
+                )
+            }
+        };
+        mvaddstr(
+            maxy - 1,
+            0,
+            &format!(
+                "{}{} | {} ms/paso | espacio: pausa, flechas: paso, +/-: velocidad, n: ronda, \
+                 r: reiniciar, q: salir",
+                status,
+                if paused { " (pausa)" } else { "" },
+                delay
+            ),
+        );
         refresh();
-        sleep(Duration::from_millis(50));
+
+        // esperamos a la siguiente tecla como mucho `delay` milisegundos
+        timeout(delay);
+        match getch() {
+            ERR if !paused => pos = (pos + 1).min(frames.len() - 1),
+            KEY_RIGHT => {
+                paused = true;
+                pos = (pos + 1).min(frames.len() - 1);
+            }
+            KEY_LEFT => {
+                paused = true;
+                pos = pos.saturating_sub(1);
+            }
+            key => match char::from_u32(key as u32) {
+                Some(' ') => paused = !paused,
+                Some('.') => {
+                    paused = true;
+                    pos = (pos + 1).min(frames.len() - 1);
+                }
+                Some(',') => {
+                    paused = true;
+                    pos = pos.saturating_sub(1);
+                }
+                Some('+') => delay = (delay / 2).max(1),
+                Some('-') => delay = (delay * 2).min(2000),
+                Some('n') => {
+                    pos = frames
+                        .iter()
+                        .position(|f| f.round > round)
+                        .unwrap_or(frames.len() - 1)
+                }
+                Some('r') => pos = 0,
+                Some('q') => break,
+                _ => (),
+            },
+        }
     }
+    timeout(-1); // volvemos a esperar a las teclas sin límite
+    result
 }
 
-// Este es nuestro punto de entrada al programa, por donde empezará a correr el ordenador el código
-fn main() {
+// Dibuja con `ncurses` todo lo que llegue por el canal y, al terminar, escribe el resultado (y lo
+// devuelve, si el combate ha llegado a terminar)
+fn watch(res: Receiver<SumoEvent>, config: SimConfig) -> Option<MatchResult> {
     initscr();                                                 // Funciones inicializadores de la
     start_color();                                             // librería gráfica `ncurses`
     init_pair(0, COLOR_GREEN, COLOR_BLACK);                    //
@@ -512,21 +1154,303 @@ fn main() {
     noecho();                                                  //
     curs_set(ncurses::CURSOR_VISIBILITY::CURSOR_INVISIBLE);    //
 
-    let res = probe_strategy( // `res` será el transmisor entre hilos
-        |_| SumoReq {
-            motor_l: 0.0,
-            motor_r: 0.0
-        }, 
-        |_| SumoReq {
-            motor_r: 0.25,
-            motor_l: 0.25
-        }
-    );
     // println!("{:?}", res); // comentario útil
-    thread::spawn(move || graphics_driver(res)).join().unwrap(); // le damos `res` al adaptador
-                                                                 // gráfico, que traducirá el
-                                                                 // estado de la batalla en
-                                                                 // gráficos `ncurses`
-    getch();  // Esperamos a que el usuario responda del shock de semejante batalla
+    // le damos `res` al adaptador gráfico, que traducirá el estado de la batalla en gráficos
+    // `ncurses`
+    //
+    // El adaptador se queda en el último paso hasta que el usuario pulse `q`, dándole tiempo a
+    // recuperarse del shock de semejante batalla
+    let result = thread::spawn(move || graphics_driver(res, &config)).join().unwrap();
     endwin(); // Terminamos la ventana del simulador
+    if let Some(result) = &result {
+        println!("{}", result); // y contamos quién ha ganado
+    }
+    result
+}
+
+// Si algo ha ido mal, lo contamos y salimos
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    })
+}
+
+// Este es nuestro punto de entrada al programa, por donde empezará a correr el ordenador el código
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+
+    // `replay <fichero>` vuelve a ver una repetición guardada con `--record`
+    if args.peek().map(String::as_str) == Some("replay") {
+        let path = or_exit(args.nth(1).ok_or("falta el fichero de la repetición".to_string()));
+        let replay = or_exit(Replay::load(&path));
+        let (tx, res) = channel();
+        replay.events.into_iter().for_each(|event| tx.send(event).unwrap());
+        watch(res, replay.config);
+        for (robot, name) in replay.names.iter().enumerate() {
+            println!("Robot {}: {}", robot + 1, name);
+        }
+        return;
+    }
+
+    // `leaderboard <fichero>` escribe la clasificación guardada con `--ratings`
+    if args.peek().map(String::as_str) == Some("leaderboard") {
+        let path = or_exit(args.nth(1).ok_or("falta el fichero de puntuaciones".to_string()));
+        print!("{}", or_exit(Ratings::load(&path)));
+        return;
+    }
+
+    // `tournament [estrategia...] [--matches <n>]` enfrenta a las estrategias registradas (todas
+    // si no se dice ninguna) y escribe la clasificación
+    //
+    // `compare <A> <B> [--samples <n>] [--jitter <fracción>]` estima la probabilidad de que gane
+    // cada una con muchos combates cambiando un poco las condiciones (ver `compare`)
+    //
+    // `optimize <estrategia> [rival...] [--method grid|random|cmaes] [--budget <n>]
+    // [--samples <n>] [--jitter <fracción>]` busca los parámetros de la estrategia que mejor salen
+    // contra los rivales (todas las registradas si no se dice ninguno, ver `optimize`)
+    let command = args.peek().map(String::as_str);
+    let (is_tournament, is_compare, is_optimize) = (
+        command == Some("tournament"),
+        command == Some("compare"),
+        command == Some("optimize"),
+    );
+    let batch = is_tournament || is_compare || is_optimize;
+    if batch {
+        args.next();
+    }
+
+    // Leemos los argumentos: `--headless`, `--record <fichero>`, `--ratings <fichero>`,
+    // `--threads <n>` (para `tournament`, `compare` y `optimize`, 0 es uno por núcleo), `--config <fichero>`,
+    // `--strategy-1 <nombre>` y `--strategy-2 <nombre>` o `--<parámetro> <valor>` para cambiar
+    // cualquier parámetro de `SimConfig` (por ejemplo `--preset mini` o `--tatami-size 30`). Se
+    // aplican en orden, así que lo último manda
+    let mut config = SimConfig::default();
+    let mut headless = false;
+    let mut record = None;
+    let mut ratings_path = None;
+    let mut names = ["idle".to_string(), "charge".to_string()];
+    let mut entrants = Vec::new();
+    let mut matches = 1;
+    let mut samples = None;
+    let mut jitter = 0.1;
+    let mut method = Method::CmaEs;
+    let mut budget = 50;
+    let mut threads = 0;
+    while let Some(arg) = args.next() {
+        let applied = match (arg.as_str(), arg.strip_prefix("--")) {
+            ("--strategy-1" | "--strategy-2", _) => match args.next() {
+                Some(name) => {
+                    names[if arg.ends_with('1') { 0 } else { 1 }] = name;
+                    Ok(())
+                }
+                None => Err(format!("falta la estrategia de `{}`", arg)),
+            },
+            ("--matches", _) if is_tournament => match args.next() {
+                Some(n) => config::parse("matches", &n).map(|n| matches = n),
+                None => Err("falta el número de `--matches`".to_string()),
+            },
+            ("--samples", _) if is_compare || is_optimize => match args.next() {
+                Some(n) => config::parse("samples", &n).map(|n| samples = Some(n)),
+                None => Err("falta el número de `--samples`".to_string()),
+            },
+            ("--method", _) if is_optimize => match args.next() {
+                Some(name) => name.parse().map(|m| method = m),
+                None => Err("falta el método de `--method`".to_string()),
+            },
+            ("--budget", _) if is_optimize => match args.next() {
+                Some(n) => config::parse("budget", &n).map(|n| budget = n),
+                None => Err("falta el número de `--budget`".to_string()),
+            },
+            ("--jitter", _) if is_compare || is_optimize => match args.next() {
+                Some(j) => config::parse("jitter", &j).map(|j| jitter = j),
+                None => Err("falta la fracción de `--jitter`".to_string()),
+            },
+            (name, None) if batch => {
+                entrants.push(name.to_string());
+                Ok(())
+            }
+            ("--headless", _) => {
+                headless = true;
+                Ok(())
+            }
+            ("--record", _) => match args.next() {
+                Some(path) => {
+                    record = Some(path);
+                    Ok(())
+                }
+                None => Err("falta el fichero de `--record`".to_string()),
+            },
+            ("--ratings", _) => match args.next() {
+                Some(path) => {
+                    ratings_path = Some(path);
+                    Ok(())
+                }
+                None => Err("falta el fichero de `--ratings`".to_string()),
+            },
+            ("--threads", _) => match args.next() {
+                Some(n) => config::parse("threads", &n).map(|n| threads = n),
+                None => Err("falta el número de `--threads`".to_string()),
+            },
+            ("--config", _) => match args.next() {
+                Some(path) => config.load(&path),
+                None => Err("falta el fichero de `--config`".to_string()),
+            },
+            (_, Some(key)) => match args.next() {
+                Some(value) => config.set(key, &value),
+                None => Err(format!("falta el valor de `{}`", arg)),
+            },
+            _ => Err(format!("argumento desconocido: `{}`", arg)),
+        };
+        or_exit(applied);
+    }
+
+    // Con `--ratings <fichero>` cada combate que termine actualiza las puntuaciones guardadas en
+    // el fichero (ver `Ratings`)
+    let mut ratings = ratings_path.as_ref().map(|path| or_exit(Ratings::load(path)));
+    let mut rate = |names: [&str; 2], result: &MatchResult| {
+        if let Some(ratings) = ratings.as_mut() {
+            ratings.record(names, result);
+        }
+    };
+
+    if is_tournament {
+        let entrants = if entrants.is_empty() {
+            strategies::names()
+        } else {
+            entrants.iter().map(String::as_str).collect()
+        };
+        print!("{}", or_exit(tournament(&entrants, matches, threads, &config, &mut rate)));
+    } else if is_compare {
+        let pair = match entrants.as_slice() {
+            [a, b] => [a.as_str(), b.as_str()],
+            _ => or_exit(Err("`compare` necesita exactamente dos estrategias".to_string())),
+        };
+        let samples = samples.unwrap_or(100);
+        print!("{}", or_exit(compare(pair, samples, jitter, threads, &config, &mut rate)));
+    } else if is_optimize {
+        let (name, pool) = match entrants.split_first() {
+            Some((name, [])) => (name.as_str(), strategies::names()),
+            Some((name, pool)) => (name.as_str(), pool.iter().map(String::as_str).collect()),
+            None => or_exit(Err("falta la estrategia que optimizar".to_string())),
+        };
+        let search = Search {
+            method,
+            budget,
+            samples: samples.unwrap_or(20),
+            jitter,
+            threads,
+        };
+        print!("{}", or_exit(optimize(name, &pool, &search, &config)));
+    } else {
+        let mut strat1 = or_exit(strategies::by_name(&names[0]));
+        let mut strat2 = or_exit(strategies::by_name(&names[1]));
+        let mut recorder = record.map(|path| {
+            or_exit(
+                Recorder::create(&path, &config, [strat1.name(), strat2.name()])
+                    .map_err(|e| format!("no se puede crear `{}`: {}", path, e)),
+            )
+        });
+        if headless {
+            // Con `--headless` no dibujamos nada, solo simulamos (grabando la repetición si nos
+            // la han pedido) y escribimos el resultado
+            let result = run_match(&mut *strat1, &mut *strat2, &config, |event| {
+                record_event(&mut recorder, &event)
+            });
+            println!("{}", result);
+            rate([&names[0], &names[1]], &result);
+        } else {
+            // `res` será el transmisor entre hilos
+            let res = probe_strategy(strat1, strat2, config.clone(), recorder);
+            if let Some(result) = watch(res, config) {
+                rate([&names[0], &names[1]], &result);
+            }
+        }
+    }
+
+    if let (Some(path), Some(ratings)) = (ratings_path, ratings) {
+        or_exit(ratings.save(&path));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    // Un robot de 1 m de lado
+    fn robot(x: f32, y: f32, dir: f32) -> SumoState {
+        SumoState::new(Vec2 { x, y }, dir, 1.0)
+    }
+
+    // Por debajo de las dos ruedas, las dos empujan hacia delante
+    #[test]
+    fn shared_speed_below_both_wheels() {
+        let v = shared_speed(-1.0, 6.0, 0.0125, [(0.0, 10.0), (0.5, 10.0)]);
+        assert!(close(v, -1.0 + 20.0 / 6.0 * 0.0125), "{}", v);
+    }
+
+    // Entre las dos ruedas, cada una empuja hacia su lado y gana la más fuerte
+    #[test]
+    fn shared_speed_between_wheels() {
+        let v = shared_speed(0.5, 6.0, 0.0125, [(0.0, 10.0), (1.0, 20.0)]);
+        assert!(close(v, 0.5 + 10.0 / 6.0 * 0.0125), "{}", v);
+    }
+
+    // Por encima de las dos ruedas, las dos frenan
+    #[test]
+    fn shared_speed_above_both_wheels() {
+        let v = shared_speed(2.0, 6.0, 0.0125, [(0.0, 10.0), (0.5, 10.0)]);
+        assert!(close(v, 2.0 - 20.0 / 6.0 * 0.0125), "{}", v);
+    }
+
+    // Un robot frenado aguanta a uno que empuja con la misma fuerza
+    #[test]
+    fn shared_speed_holds_at_a_wheel_speed() {
+        assert_eq!(shared_speed(0.0, 6.0, 0.0125, [(0.0, 10.0), (1.0, 10.0)]), 0.0);
+    }
+
+    // Las esquinas van en el orden de `Corners` también con el robot girado: mirando hacia `y`, la
+    // delantera izquierda es la de arriba a la izquierda
+    #[test]
+    fn corners_after_quarter_turn() {
+        let state = SumoState::new(Vec2 { x: 1.0, y: 2.0 }, PI / 2.0, 0.5);
+        let expected = [(0.75, 2.25), (1.25, 2.25), (1.25, 1.75), (0.75, 1.75)];
+        for (corner, (x, y)) in state.corners.iter().zip(expected) {
+            let ok = close(corner.x, x) && close(corner.y, y);
+            assert!(ok, "{:?} en vez de ({}, {})", corner, x, y);
+        }
+    }
+
+    // Dos robots separados no chocan, aunque uno esté girado y sus cajas alineadas con los ejes
+    // se solapen (solo el eje de su diagonal los separa)
+    #[test]
+    fn collide_separated() {
+        assert!(collide(robot(0.0, 0.0, 0.0), robot(1.5, 0.0, 0.0)).is_none());
+        assert!(collide(robot(0.0, 0.0, 0.0), robot(1.05, 1.05, PI / 4.0)).is_none());
+    }
+
+    // Dos robots alineados que se meten 20 cm el uno en el otro, con la normal de `a` a `b`
+    #[test]
+    fn collide_overlap() {
+        let (a, b) = (robot(0.0, 0.0, 0.0), robot(0.8, 0.0, 0.0));
+        let contact = collide(a, b).unwrap();
+        assert!(close(contact.normal.x, 1.0) && close(contact.normal.y, 0.0));
+        assert!(close(contact.depth, 0.2), "{}", contact.depth);
+        let contact = collide(b, a).unwrap();
+        assert!(close(contact.normal.x, -1.0) && close(contact.normal.y, 0.0));
+    }
+
+    // Un robot girado 45º que mete una esquina en el frontal del otro: el choque va a lo largo
+    // del eje del primero y la profundidad es lo que entra la esquina
+    #[test]
+    fn collide_rotated_overlap() {
+        let contact = collide(robot(0.0, 0.0, 0.0), robot(1.1, 0.0, PI / 4.0)).unwrap();
+        assert!(close(contact.normal.x, 1.0) && close(contact.normal.y, 0.0));
+        let depth = 0.5 - (1.1 - 0.5 * 2f32.sqrt());
+        assert!(close(contact.depth, depth), "{}", contact.depth);
+    }
 }
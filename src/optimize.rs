@@ -0,0 +1,459 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    compare::{check_jitter, sample_jobs},
+    run_batch,
+    strategies::{self, Param},
+    Rng, SimConfig, Tally,
+};
+
+// =======================================================================================================
+// Constantes
+// =======================================================================================================
+
+const SHOWN: usize = 10;        // Cuántos de los mejores candidatos se enseñan al terminar
+const SIGMA_INIT: f32 = 0.3;    // Tamaño inicial de los pasos de CMA-ES (en fracciones del rango
+                                // de cada parámetro)
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Cómo elegir qué valores de los parámetros probar
+#[derive(Clone, Copy, Debug)]
+pub enum Method {
+    Grid,   // una rejilla regular que cubre todo el rango de cada parámetro
+    Random, // valores al azar dentro de los rangos
+    CmaEs,  // CMA-ES: va moviendo y deformando una nube de candidatos hacia donde mejor salen
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Method, String> {
+        match name {
+            "grid" => Ok(Method::Grid),
+            "random" => Ok(Method::Random),
+            "cmaes" => Ok(Method::CmaEs),
+            _ => Err(format!("no existe el método `{}` (hay: grid, random, cmaes)", name)),
+        }
+    }
+}
+
+// Unos valores de los parámetros y lo que han sacado contra los rivales
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub spec: String, // la estrategia con sus parámetros, tal y como la entiende `by_name`
+    pub tally: Tally,
+}
+
+impl Candidate {
+    // Lo que se maximiza: la proporción de combates ganados, contando los empates como medio
+    pub fn score(&self) -> f32 {
+        (self.tally.wins as f32 + self.tally.draws as f32 / 2.0) / self.tally.samples().max(1) as f32
+    }
+}
+
+// Cómo buscar: con qué método, cuántos candidatos probar como mucho (`budget`), cuántos combates
+// juega cada uno contra cada rival (`samples`), cuánto se cambian las condiciones de cada combate
+// (`jitter`, ver `compare`) y entre cuántos hilos se reparten (`threads`, ver `run_batch`)
+#[derive(Clone, Copy, Debug)]
+pub struct Search {
+    pub method: Method,
+    pub budget: u32,
+    pub samples: u32,
+    pub jitter: f32,
+    pub threads: usize,
+}
+
+// El resultado de una búsqueda: los valores por defecto y todos los candidatos, de mejor a peor
+pub struct Optimization {
+    pub baseline: Candidate,
+    pub candidates: Vec<Candidate>,
+}
+
+// Busca los valores de los parámetros de la estrategia `name` que mejor salen contra los rivales
+// de `pool`
+//
+// Cada candidato juega contra cada rival los mismos combates que jugaría en `compare` (las mismas
+// salidas, ruido y física), así las diferencias entre candidatos son por sus parámetros y no por
+// la suerte
+//
+// Los métodos trabajan con cada parámetro pasado a [0, 1] (0 es su mínimo y 1 su máximo), para
+// que todos pesen lo mismo aunque tengan unidades distintas
+pub fn optimize(
+    name: &str,
+    pool: &[&str],
+    search: &Search,
+    config: &SimConfig,
+) -> Result<Optimization, String> {
+    let params = strategies::params(name)?;
+    if params.is_empty() {
+        return Err(format!("`{}` no tiene parámetros que ajustar", name));
+    }
+    if pool.is_empty() || search.samples == 0 || search.budget == 0 {
+        return Err(
+            "hacen falta rivales, combates (`--samples`) y candidatos (`--budget`)".to_string(),
+        );
+    }
+    check_jitter(search.jitter)?;
+    let evaluate = |points: &[Vec<f32>]| evaluate(name, params, points, pool, search, config);
+
+    let defaults: Vec<f32> = params
+        .iter()
+        .map(|p| (p.default - p.min) / (p.max - p.min).max(f32::EPSILON))
+        .collect();
+    let mut baseline = evaluate(std::slice::from_ref(&defaults))?.remove(0);
+    baseline.spec = name.to_string(); // sin parámetros, con los de por defecto
+    let mut rng = Rng::new(config.seed).fork(u64::MAX); // aparte de los de los combates
+    let mut candidates = match search.method {
+        Method::Grid => evaluate(&grid(params.len(), search.budget, &mut rng))?,
+        Method::Random => {
+            let points: Vec<Vec<f32>> = (0..search.budget)
+                .map(|_| (0..params.len()).map(|_| rng.uniform()).collect())
+                .collect();
+            evaluate(&points)?
+        }
+        Method::CmaEs => cma_es(defaults, search.budget, &mut rng, evaluate)?,
+    };
+    candidates.sort_by(|a, b| b.score().total_cmp(&a.score()));
+    Ok(Optimization { baseline, candidates })
+}
+
+// Juega todos los combates de una tanda de candidatos (puntos en [0, 1]) contra todos los rivales
+fn evaluate(
+    name: &str,
+    params: &[Param],
+    points: &[Vec<f32>],
+    pool: &[&str],
+    search: &Search,
+    config: &SimConfig,
+) -> Result<Vec<Candidate>, String> {
+    let specs = points
+        .iter()
+        .map(|point| {
+            let values: Vec<f32> = params
+                .iter()
+                .zip(point)
+                .map(|(p, x)| p.min + x.clamp(0.0, 1.0) * (p.max - p.min))
+                .collect();
+            strategies::spec(name, &values)
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+    let (jobs, seats): (Vec<_>, Vec<_>) = specs
+        .iter()
+        .flat_map(|spec| {
+            pool.iter().flat_map(|&rival| {
+                sample_jobs([spec, rival], search.samples, search.jitter, config)
+            })
+        })
+        .unzip();
+    let results = run_batch(&jobs, search.threads)?;
+
+    // los combates van en orden: todos los del primer candidato, luego los del segundo...
+    let per_candidate = pool.len() * search.samples as usize;
+    Ok(specs
+        .into_iter()
+        .enumerate()
+        .map(|(c, spec)| {
+            let mut tally = Tally::new(&spec);
+            for n in c * per_candidate..(c + 1) * per_candidate {
+                tally.add(results[n].winner.map(|winner| winner == seats[n]));
+            }
+            Candidate { spec, tally }
+        })
+        .collect())
+}
+
+// Una rejilla con el mismo número de valores en cada parámetro (al menos 2, los extremos) y como
+// mucho `budget` puntos. Si ni con 2 valores por parámetro cabe, nos quedamos con `budget` puntos
+// de la rejilla escogidos al azar
+fn grid(dims: usize, budget: u32, rng: &mut Rng) -> Vec<Vec<f32>> {
+    let mut levels = 2;
+    while (levels + 1u32).pow(dims as u32) <= budget {
+        levels += 1;
+    }
+    let mut points = vec![vec![]];
+    for _ in 0..dims {
+        points = points
+            .into_iter()
+            .flat_map(|point: Vec<f32>| {
+                (0..levels).map(move |i| {
+                    let mut point = point.clone();
+                    point.push(i as f32 / (levels - 1) as f32);
+                    point
+                })
+            })
+            .collect();
+    }
+    let budget = budget as usize;
+    if points.len() > budget {
+        // los primeros `budget` de una permutación al azar (Fisher-Yates), en el orden de la rejilla
+        let mut chosen: Vec<usize> = (0..points.len()).collect();
+        for i in 0..budget {
+            let last = chosen.len() - 1;
+            let j = i + (rng.uniform() * (chosen.len() - i) as f32) as usize;
+            chosen.swap(i, j.min(last));
+        }
+        chosen.truncate(budget);
+        chosen.sort_unstable();
+        points = chosen.into_iter().map(|i| points[i].clone()).collect();
+    }
+    points
+}
+
+// CMA-ES (Hansen) con sus ajustes recomendados, empezando en `start` y maximizando la puntuación
+//
+// Cada generación saca `lambda` candidatos de una normal con media `mean` y covarianza
+// `sigma² · C`, mueve la media hacia los mejores y deforma `C` para que la nube se estire en la
+// dirección en la que se ha ido mejorando. Se para cuando ya no cabe otra generación entera en
+// `budget` (así que pueden sobrar hasta `lambda - 1` candidatos), y si no cabe ni una es un error
+fn cma_es(
+    start: Vec<f32>,
+    budget: u32,
+    rng: &mut Rng,
+    evaluate: impl Fn(&[Vec<f32>]) -> Result<Vec<Candidate>, String>,
+) -> Result<Vec<Candidate>, String> {
+    let n = start.len();
+    let nf = n as f32;
+    let lambda = 4 + (3.0 * nf.ln()).floor() as usize;
+    let mu = lambda / 2;
+    if (budget as usize) < lambda {
+        return Err(format!(
+            "CMA-ES prueba {} candidatos por generación, `--budget` tiene que ser al menos eso",
+            lambda
+        ));
+    }
+    let weights: Vec<f32> = {
+        let raw: Vec<f32> = (1..=mu).map(|i| (mu as f32 + 0.5).ln() - (i as f32).ln()).collect();
+        let sum: f32 = raw.iter().sum();
+        raw.iter().map(|w| w / sum).collect()
+    };
+    let mueff = 1.0 / weights.iter().map(|w| w * w).sum::<f32>();
+    let cc = (4.0 + mueff / nf) / (nf + 4.0 + 2.0 * mueff / nf);
+    let cs = (mueff + 2.0) / (nf + mueff + 5.0);
+    let c1 = 2.0 / ((nf + 1.3).powi(2) + mueff);
+    let cmu = (1.0 - c1).min(2.0 * (mueff - 2.0 + 1.0 / mueff) / ((nf + 2.0).powi(2) + mueff));
+    let damps = 1.0 + 2.0 * (((mueff - 1.0) / (nf + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+    let chi_n = nf.sqrt() * (1.0 - 1.0 / (4.0 * nf) + 1.0 / (21.0 * nf * nf));
+
+    let mut mean = start;
+    let mut sigma = SIGMA_INIT;
+    let mut cov = identity(n);
+    let (mut pc, mut ps) = (vec![0.0; n], vec![0.0; n]);
+    let mut all = Vec::new();
+    let mut generation = 0;
+    while all.len() + lambda <= budget as usize {
+        generation += 1;
+
+        // C = B·D²·Bᵀ, así y = B·D·z con z normal tiene covarianza C
+        let (eigenvalues, basis) = eigen(&cov);
+        let d: Vec<f32> = eigenvalues.iter().map(|e| e.max(0.0).sqrt().max(1e-10)).collect();
+        let steps: Vec<Vec<f32>> = (0..lambda)
+            .map(|_| {
+                let z: Vec<f32> = (0..n).map(|k| d[k] * rng.gaussian(1.0)).collect();
+                (0..n).map(|i| (0..n).map(|k| basis[i][k] * z[k]).sum()).collect()
+            })
+            .collect();
+        let points: Vec<Vec<f32>> = steps
+            .iter()
+            .map(|y| (0..n).map(|i| mean[i] + sigma * y[i]).collect())
+            .collect();
+        let candidates = evaluate(&points)?;
+
+        // los `mu` mejores, cada uno con su peso
+        let mut order: Vec<usize> = (0..lambda).collect();
+        order.sort_by(|&a, &b| candidates[b].score().total_cmp(&candidates[a].score()));
+        let y_w: Vec<f32> = (0..n)
+            .map(|i| (0..mu).map(|k| weights[k] * steps[order[k]][i]).sum())
+            .collect();
+        for i in 0..n {
+            mean[i] += sigma * y_w[i];
+        }
+
+        // caminos de evolución: por dónde se ha ido moviendo la media últimamente
+        let whitened: Vec<f32> = {
+            // C^(-1/2)·y_w = B·D⁻¹·Bᵀ·y_w
+            let projected: Vec<f32> = (0..n)
+                .map(|k| (0..n).map(|i| basis[i][k] * y_w[i]).sum::<f32>() / d[k])
+                .collect();
+            (0..n).map(|i| (0..n).map(|k| basis[i][k] * projected[k]).sum()).collect()
+        };
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        for i in 0..n {
+            ps[i] = (1.0 - cs) * ps[i] + (cs * (2.0 - cs) * mueff).sqrt() * whitened[i];
+        }
+        let stalled = norm(&ps) / (1.0 - (1.0 - cs).powi(2 * generation)).sqrt() / chi_n
+            >= 1.4 + 2.0 / (nf + 1.0);
+        let h_sigma = if stalled { 0.0 } else { 1.0 };
+        for i in 0..n {
+            pc[i] = (1.0 - cc) * pc[i] + h_sigma * (cc * (2.0 - cc) * mueff).sqrt() * y_w[i];
+        }
+
+        // la nueva covarianza y el nuevo tamaño de paso
+        for i in 0..n {
+            for j in 0..n {
+                let rank_mu: f32 = (0..mu)
+                    .map(|k| weights[k] * steps[order[k]][i] * steps[order[k]][j])
+                    .sum();
+                cov[i][j] = (1.0 - c1 - cmu) * cov[i][j]
+                    + c1 * (pc[i] * pc[j] + (1.0 - h_sigma) * cc * (2.0 - cc) * cov[i][j])
+                    + cmu * rank_mu;
+            }
+        }
+        sigma *= ((cs / damps) * (norm(&ps) / chi_n - 1.0)).exp();
+        all.extend(candidates);
+    }
+    Ok(all)
+}
+
+fn identity(n: usize) -> Vec<Vec<f32>> {
+    (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect()
+}
+
+// Valores y vectores propios de una matriz simétrica (método de Jacobi): devuelve los valores y
+// una matriz con los vectores en sus columnas
+fn eigen(matrix: &[Vec<f32>]) -> (Vec<f32>, Vec<Vec<f32>>) {
+    let n = matrix.len();
+    let mut a = matrix.to_vec();
+    let mut v = identity(n);
+    for _ in 0..50 * n * n {
+        // el elemento fuera de la diagonal más grande ...
+        let (mut p, mut q, mut largest) = (0, 0, 0.0);
+        for (i, row) in a.iter().enumerate() {
+            for (j, x) in row.iter().enumerate().skip(i + 1) {
+                if x.abs() > largest {
+                    (p, q, largest) = (i, j, x.abs());
+                }
+            }
+        }
+        if largest < 1e-9 {
+            break;
+        }
+        // ... lo hacemos 0 con un giro en el plano (p, q)
+        let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+        let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+        let (c, s) = (1.0 / (t * t + 1.0).sqrt(), t / (t * t + 1.0).sqrt());
+        for row in a.iter_mut().chain(v.iter_mut()) {
+            let (vkp, vkq) = (row[p], row[q]);
+            row[p] = c * vkp - s * vkq;
+            row[q] = s * vkp + c * vkq;
+        }
+        let (row_p, row_q) = (a[p].clone(), a[q].clone());
+        a[p] = row_p.iter().zip(&row_q).map(|(x, y)| c * x - s * y).collect();
+        a[q] = row_p.iter().zip(&row_q).map(|(x, y)| s * x + c * y).collect();
+    }
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+impl fmt::Display for Optimization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} candidatos, {} combates cada uno; puntuación = (ganados + empates / 2) / combates",
+            self.candidates.len(),
+            self.baseline.tally.samples()
+        )?;
+        writeln!(f, "{:>3}  {:>10}  {:>20}  estrategia", "#", "puntuación", "ganados")?;
+        let row = |f: &mut fmt::Formatter<'_>, position: String, c: &Candidate| {
+            let (p, low, high) = c.tally.rate(c.tally.wins);
+            writeln!(
+                f,
+                "{:>3}  {:>9.1}%  {:>20}  {}",
+                position,
+                100.0 * c.score(),
+                format!("{:.1}% [{:.1}, {:.1}]", 100.0 * p, 100.0 * low, 100.0 * high),
+                c.spec
+            )
+        };
+        row(f, "-".to_string(), &self.baseline)?;
+        for (position, c) in self.candidates.iter().take(SHOWN).enumerate() {
+            row(f, (position + 1).to_string(), c)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cada valor propio con su vector cumple A·v = λ·v, y los vectores son ortonormales
+    #[test]
+    fn eigen_decomposition() {
+        let matrix = vec![
+            vec![4.0, 1.0, 0.5],
+            vec![1.0, 3.0, -0.2],
+            vec![0.5, -0.2, 1.0],
+        ];
+        let (values, vectors) = eigen(&matrix);
+        for k in 0..3 {
+            for i in 0..3 {
+                let av: f32 = (0..3).map(|j| matrix[i][j] * vectors[j][k]).sum();
+                assert!((av - values[k] * vectors[i][k]).abs() < 1e-4, "λ{} fila {}", k, i);
+            }
+            for l in 0..3 {
+                let dot: f32 = (0..3).map(|i| vectors[i][k] * vectors[i][l]).sum();
+                let expected = if k == l { 1.0 } else { 0.0 };
+                assert!((dot - expected).abs() < 1e-4, "v{} · v{} = {}", k, l, dot);
+            }
+        }
+        let trace: f32 = values.iter().sum();
+        assert!((trace - 8.0).abs() < 1e-4);
+    }
+
+    // Con presupuesto de sobra, la rejilla más fina que cabe: 10 puntos en 2 parámetros dan 3
+    // valores por parámetro (3² = 9 ≤ 10 < 4²)
+    #[test]
+    fn grid_levels() {
+        let points = grid(2, 10, &mut Rng::new(0));
+        assert_eq!(points.len(), 9);
+        for x in [0.0, 0.5, 1.0] {
+            for y in [0.0, 0.5, 1.0] {
+                assert!(points.contains(&vec![x, y]), "falta ({}, {})", x, y);
+            }
+        }
+    }
+
+    // Si ni con los extremos cabe, se queda con `budget` esquinas distintas de la rejilla
+    #[test]
+    fn grid_over_budget() {
+        let points = grid(3, 5, &mut Rng::new(0));
+        assert_eq!(points.len(), 5);
+        for (n, point) in points.iter().enumerate() {
+            assert!(point.iter().all(|&x| x == 0.0 || x == 1.0), "{:?}", point);
+            assert!(!points[..n].contains(point), "{:?} repetido", point);
+        }
+    }
+
+    // Un candidato de mentira que saca más cuanto más cerca está de (0.7, 0.2)
+    fn fake_evaluate(points: &[Vec<f32>]) -> Result<Vec<Candidate>, String> {
+        Ok(points
+            .iter()
+            .map(|point| {
+                let distance = ((point[0] - 0.7).powi(2) + (point[1] - 0.2).powi(2)).sqrt();
+                let mut tally = Tally::new("todas");
+                tally.wins = (100.0 * (1.0 - distance).max(0.0)) as u32;
+                tally.losses = 100 - tally.wins;
+                Candidate { spec: format!("{:?}", point), tally }
+            })
+            .collect())
+    }
+
+    // CMA-ES nunca prueba más candidatos que `budget`, y si no cabe ni una generación lo dice
+    #[test]
+    fn cma_es_budget() {
+        let lambda = 4 + (3.0 * 2f32.ln()).floor() as usize; // 6 con 2 parámetros
+        for budget in [lambda, lambda + 1, 2 * lambda - 1, 50] {
+            let all = cma_es(vec![0.5, 0.5], budget as u32, &mut Rng::new(1), fake_evaluate);
+            let all = all.unwrap();
+            assert!(all.len() <= budget, "{} candidatos con `budget` {}", all.len(), budget);
+            assert!(all.len() + lambda > budget);
+        }
+        assert!(cma_es(vec![0.5, 0.5], 2, &mut Rng::new(1), fake_evaluate).is_err());
+    }
+}
@@ -0,0 +1,271 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::{collections::BTreeMap, f64::consts::PI, fmt, fs};
+
+use crate::{config::parse, strategies, MatchResult};
+
+// =======================================================================================================
+// Constantes
+// =======================================================================================================
+
+const SCALE: f64 = 173.7178;           // Paso de la escala de Glicko a la de Glicko-2
+const TAU: f64 = 0.5;                  // Cuánto puede cambiar la volatilidad de un combate a otro
+const EPSILON: f64 = 0.000001;         // Precisión al calcular la nueva volatilidad
+pub(crate) const Z_95: f64 = 1.96;     // Desviaciones que abarcan el 95% de una normal (también
+                                       // para los intervalos de `compare`)
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// La puntuación de una estrategia según Glicko-2: no solo dice cómo de buena parece (`rating`),
+// también cuánto nos fiamos de ese número (`deviation`, que baja a medida que juega) y cómo de
+// irregulares son sus resultados (`volatility`)
+//
+// Empieza en 1500 ± 350, como en el ajedrez
+#[derive(Clone, Copy, Debug)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+    pub matches: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: 1500.0,
+            deviation: 350.0,
+            volatility: 0.06,
+            matches: 0,
+        }
+    }
+}
+
+impl Rating {
+    // El intervalo en el que, con un 95% de confianza, está la puntuación de verdad. Si los
+    // intervalos de dos estrategias no se solapan, podemos fiarnos de que una es mejor
+    pub fn interval(&self) -> (f64, f64) {
+        (
+            self.rating - Z_95 * self.deviation,
+            self.rating + Z_95 * self.deviation,
+        )
+    }
+
+    // La puntuación tras un combate contra `opponent` en el que hemos sacado `score` (1 si
+    // ganamos, 0 si perdemos y 0.5 si empatamos), con cada combate como un periodo
+    pub fn update(self, opponent: Rating, score: f64) -> Rating {
+        self.update_period(&[(opponent, score)])
+    }
+
+    // La puntuación tras un periodo con varios combates (cada uno con el rival y lo que hemos
+    // sacado), siguiendo el algoritmo de Glickman
+    pub fn update_period(self, games: &[(Rating, f64)]) -> Rating {
+        let (mu, phi) = ((self.rating - 1500.0) / SCALE, self.deviation / SCALE);
+
+        // lo que esperábamos sacar en cada combate y cuánto nos han enseñado
+        let (mut information, mut surprise) = (0.0, 0.0);
+        for &(opponent, score) in games {
+            let (mu_j, phi_j) = ((opponent.rating - 1500.0) / SCALE, opponent.deviation / SCALE);
+            let g = 1.0 / (1.0 + 3.0 * phi_j * phi_j / (PI * PI)).sqrt();
+            let expected = 1.0 / (1.0 + (-g * (mu - mu_j)).exp());
+            information += g * g * expected * (1.0 - expected);
+            surprise += g * (score - expected);
+        }
+        let v = 1.0 / information;
+        let delta = v * surprise;
+
+        // la nueva volatilidad (método de Illinois para encontrar el cero de `f`)
+        let a = (self.volatility * self.volatility).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+                - (x - a) / (TAU * TAU)
+        };
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * TAU
+        };
+        let (mut f_a, mut f_b) = (f(big_a), f(big_b));
+        while (big_b - big_a).abs() > EPSILON {
+            let c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(c);
+            if f_c * f_b <= 0.0 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+            big_b = c;
+            f_b = f_c;
+        }
+        let volatility = (big_a / 2.0).exp();
+
+        // y con ella la nueva incertidumbre y la nueva puntuación
+        let phi_star = (phi * phi + volatility * volatility).sqrt();
+        let phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let mu = mu + phi * phi * surprise;
+        Rating {
+            rating: mu * SCALE + 1500.0,
+            deviation: phi * SCALE,
+            volatility,
+            matches: self.matches + games.len() as u32,
+        }
+    }
+}
+
+// Las puntuaciones de todas las estrategias que han jugado alguna vez, guardadas en un fichero de
+// texto con una línea por estrategia:
+//
+// ```text
+// <nombre> <puntuación> <desviación> <volatilidad> <combates>
+// ```
+#[derive(Clone, Debug, Default)]
+pub struct Ratings {
+    pub table: BTreeMap<String, Rating>,
+}
+
+impl Ratings {
+    // Lee las puntuaciones de `path`; si el fichero no existe todavía, empezamos de cero
+    pub fn load(path: &str) -> Result<Ratings, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Ratings::default()),
+            Err(e) => return Err(format!("no se puede leer `{}`: {}", path, e)),
+        };
+        let mut ratings = Ratings::default();
+        for (n, line) in text.lines().enumerate() {
+            let err = |e: String| format!("{}:{}: {}", path, n + 1, e);
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[..] {
+                [] => (),
+                [first, ..] if first.starts_with('#') => (),
+                [name, rating, deviation, volatility, matches] => {
+                    let rating = Rating {
+                        rating: parse("puntuación", rating).map_err(err)?,
+                        deviation: parse("desviación", deviation).map_err(err)?,
+                        volatility: parse("volatilidad", volatility).map_err(err)?,
+                        matches: parse("combates", matches).map_err(err)?,
+                    };
+                    ratings.table.insert(name.to_string(), rating);
+                }
+                _ => return Err(err("se esperaba `nombre puntuación desviación volatilidad \
+                                     combates`".to_string())),
+            }
+        }
+        Ok(ratings)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut text = "# puntuaciones de sumo-strategy-prober (Glicko-2)\n".to_string();
+        for (name, r) in &self.table {
+            text += &format!(
+                "{} {} {} {} {}\n",
+                name, r.rating, r.deviation, r.volatility, r.matches
+            );
+        }
+        fs::write(path, text).map_err(|e| format!("no se puede escribir `{}`: {}", path, e))
+    }
+
+    // Actualiza las puntuaciones de las dos estrategias con el resultado de un combate entre ellas
+    // (las dos con la puntuación que tenían antes del combate)
+    //
+    // Cada estrategia se apunta con su nombre canónico (ver `strategies::canonical`): así la misma
+    // estrategia escrita de dos maneras cuenta como una, y en el fichero no acaban espacios que
+    // luego no se podrían leer
+    pub fn record(&mut self, names: [&str; 2], result: &MatchResult) {
+        let names = names.map(|name| {
+            strategies::canonical(name).unwrap_or_else(|_| name.split_whitespace().collect())
+        });
+        let names = [names[0].as_str(), names[1].as_str()];
+        if names[0] == names[1] {
+            return; // contra sí misma no aprende nada
+        }
+        let before = names.map(|name| self.table.get(name).copied().unwrap_or_default());
+        for robot in 0..2 {
+            let score = match result.winner {
+                Some(winner) if winner == robot => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+            let after = before[robot].update(before[1 - robot], score);
+            self.table.insert(names[robot].to_string(), after);
+        }
+    }
+}
+
+// La clasificación, de mayor a menor puntuación
+impl fmt::Display for Ratings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows: Vec<_> = self.table.iter().collect();
+        rows.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating));
+        let width = rows.iter().map(|(name, _)| name.len()).chain([10]).max().unwrap_or(10);
+        writeln!(
+            f,
+            "{:>3}  {:<width$}  {:>10}  {:>8}  {:>19}  {:>8}",
+            "#", "estrategia", "puntuación", "± 95%", "intervalo", "combates"
+        )?;
+        for (position, (name, r)) in rows.into_iter().enumerate() {
+            let (low, high) = r.interval();
+            writeln!(
+                f,
+                "{:>3}  {:<width$}  {:>10.0}  {:>8.0}  {:>19}  {:>8}",
+                position + 1,
+                name,
+                r.rating,
+                Z_95 * r.deviation,
+                format!("[{:.0}, {:.0}]", low, high),
+                r.matches
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            ..Rating::default()
+        }
+    }
+
+    // El ejemplo del artículo de Glickman ("Example of the Glicko-2 system"): 1500 ± 200 gana a
+    // 1400 ± 30 y pierde contra 1550 ± 100 y 1700 ± 300
+    #[test]
+    fn glickman_example() {
+        let player = rating(1500.0, 200.0);
+        let games = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+        let after = player.update_period(&games);
+        assert!((after.rating - 1464.06).abs() < 0.01, "{}", after.rating);
+        assert!((after.deviation - 151.52).abs() < 0.01, "{}", after.deviation);
+        assert!((after.volatility - 0.05999).abs() < 0.00001, "{}", after.volatility);
+        assert_eq!(after.matches, 3);
+    }
+
+    // Un combate suelto es un periodo con un solo combate, y quien gana sube y se vuelve más fiable
+    #[test]
+    fn single_match() {
+        let (a, b) = (Rating::default(), Rating::default());
+        let after = a.update(b, 1.0);
+        assert_eq!(after.rating, a.update_period(&[(b, 1.0)]).rating);
+        assert!(after.rating > a.rating);
+        assert!(after.deviation < a.deviation);
+    }
+}
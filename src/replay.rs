@@ -0,0 +1,214 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    str::FromStr,
+};
+
+use crate::{
+    Frame, MatchResult, RoundEnd, RoundResult, SensorFrame, SimConfig, SumoEvent, SumoReq,
+    SumoState, Vec2,
+};
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Las repeticiones son ficheros de texto, una línea por cosa que pasa:
+//
+// ```text
+// config <clave> <valor>           (una por cada parámetro de `SimConfig`)
+// strategy <robot> <nombre>        (robot 1 o 2)
+// frame <ronda> <paso> <x> <y> <dir> <lado> (por robot) <n> <distancia>... <m> <línea>...
+//       (n lecturas de distancia y m de línea, 0 o 1, por robot) <izq> <der> (por robot)
+// round <ronda> <ganador> <perdedor> <causa> <pasos>   (`-` si no hay ganador/perdedor)
+// ```
+//
+// Las líneas que empiezan por `#` se ignoran
+
+// Va escribiendo la repetición mientras se simula
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    // Crea el fichero y escribe la cabecera: la configuración y los nombres de las estrategias
+    pub fn create(path: &str, config: &SimConfig, names: [&str; 2]) -> io::Result<Recorder> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# repetición de sumo-strategy-prober")?;
+        for (key, value) in config.entries() {
+            writeln!(out, "config {} {}", key, value)?;
+        }
+        for (robot, name) in names.iter().enumerate() {
+            writeln!(out, "strategy {} {}", robot + 1, name)?;
+        }
+        Ok(Recorder { out })
+    }
+
+    pub fn record(&mut self, event: &SumoEvent) -> io::Result<()> {
+        match event {
+            SumoEvent::Frame(frame) => {
+                write!(self.out, "frame {} {}", frame.round, frame.tick)?;
+                for s in frame.states {
+                    write!(self.out, " {} {} {} {}", s.center.x, s.center.y, s.dir, s.size)?;
+                }
+                for sensors in &frame.sensors {
+                    write!(self.out, " {}", sensors.distance.len())?;
+                    for d in &sensors.distance {
+                        write!(self.out, " {}", d)?;
+                    }
+                    write!(self.out, " {}", sensors.line.len())?;
+                    for &l in &sensors.line {
+                        write!(self.out, " {}", l as u8)?;
+                    }
+                }
+                for req in frame.reqs {
+                    write!(self.out, " {} {}", req.motor_l, req.motor_r)?;
+                }
+                writeln!(self.out)
+            }
+            SumoEvent::Round(result) => {
+                let robot = |r: Option<usize>| r.map_or("-".to_string(), |r| (r + 1).to_string());
+                writeln!(
+                    self.out,
+                    "round {} {} {} {:?} {}",
+                    result.round,
+                    robot(result.winner),
+                    robot(result.loser),
+                    result.cause,
+                    result.ticks
+                )
+            }
+            // el resultado del combate se puede deducir de las rondas, solo nos aseguramos de
+            // que todo llegue al fichero
+            SumoEvent::Match(_) => self.out.flush(),
+        }
+    }
+}
+
+// Una repetición leída de un fichero, lista para dársela al adaptador gráfico
+pub struct Replay {
+    pub config: SimConfig,
+    pub names: [String; 2],
+    pub events: Vec<SumoEvent>,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Replay, String> {
+        let text =
+            fs::read_to_string(path).map_err(|e| format!("no se puede leer `{}`: {}", path, e))?;
+        let mut replay = Replay {
+            config: SimConfig::default(),
+            names: [String::new(), String::new()],
+            events: Vec::new(),
+        };
+        let mut rounds = Vec::new();
+        let mut last_states = None;
+        for (n, line) in text.lines().enumerate() {
+            let err = |e: String| format!("{}:{}: {}", path, n + 1, e);
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                None => (),
+                Some(word) if word.starts_with('#') => (),
+                Some("config") => {
+                    let key = next::<String>(&mut fields).map_err(err)?;
+                    let value = fields.collect::<Vec<_>>().join(" ");
+                    replay.config.set(&key, &value).map_err(err)?;
+                }
+                Some("strategy") => {
+                    let robot = robot(&mut fields)
+                        .map_err(err)?
+                        .ok_or_else(|| err("falta el robot".to_string()))?;
+                    replay.names[robot] = fields.collect::<Vec<_>>().join(" ");
+                }
+                Some("frame") => {
+                    let frame = parse_frame(&mut fields).map_err(err)?;
+                    last_states = Some(frame.states);
+                    replay.events.push(SumoEvent::Frame(frame));
+                }
+                Some("round") => {
+                    let mut result = RoundResult {
+                        round: next(&mut fields).map_err(err)?,
+                        winner: robot(&mut fields).map_err(err)?,
+                        loser: robot(&mut fields).map_err(err)?,
+                        cause: match next::<String>(&mut fields).map_err(err)?.as_str() {
+                            "PushedOut" => RoundEnd::PushedOut,
+                            "DroveOut" => RoundEnd::DroveOut,
+                            "Draw" => RoundEnd::Draw,
+                            "Timeout" => RoundEnd::Timeout,
+                            "FalseStart" => RoundEnd::FalseStart,
+                            cause => return Err(err(format!("causa desconocida: `{}`", cause))),
+                        },
+                        ticks: next(&mut fields).map_err(err)?,
+                        time: 0.0,
+                        final_states: last_states
+                            .ok_or_else(|| err("ronda sin ningún paso".to_string()))?,
+                    };
+                    // el tiempo no se guarda, sale de los pasos
+                    result.time = result.ticks as f32 * replay.config.dt;
+                    rounds.push(result);
+                    replay.events.push(SumoEvent::Round(result));
+                }
+                Some(word) => return Err(err(format!("línea desconocida: `{}`", word))),
+            }
+        }
+        replay.events.push(SumoEvent::Match(MatchResult::from_rounds(rounds)));
+        Ok(replay)
+    }
+}
+
+// Lee el siguiente campo de la línea
+fn next<'a, T: FromStr>(fields: &mut impl Iterator<Item = &'a str>) -> Result<T, String> {
+    let field = fields.next().ok_or("faltan campos")?;
+    field
+        .parse()
+        .map_err(|_| format!("valor inválido: `{}`", field))
+}
+
+// Lee un número de robot (1 o 2, `-` si no hay ninguno) y lo convierte en un índice
+fn robot<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Result<Option<usize>, String> {
+    match next::<String>(fields)?.as_str() {
+        "-" => Ok(None),
+        "1" => Ok(Some(0)),
+        "2" => Ok(Some(1)),
+        robot => Err(format!("robot inválido: `{}`", robot)),
+    }
+}
+
+fn parse_frame<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Result<Frame, String> {
+    let round = next(fields)?;
+    let tick = next(fields)?;
+    let mut state = || -> Result<SumoState, String> {
+        let center = Vec2 {
+            x: next(fields)?,
+            y: next(fields)?,
+        };
+        Ok(SumoState::new(center, next(fields)?, next(fields)?))
+    };
+    let states = [state()?, state()?];
+    let mut sensors = || -> Result<SensorFrame, String> {
+        let n: usize = next(fields)?;
+        let distance = (0..n).map(|_| next(fields)).collect::<Result<_, _>>()?;
+        let m: usize = next(fields)?;
+        let line = (0..m).map(|_| next::<u8>(fields).map(|l| l != 0)).collect::<Result<_, _>>()?;
+        Ok(SensorFrame { distance, line })
+    };
+    let sensors = [sensors()?, sensors()?];
+    let mut req = || -> Result<SumoReq, String> {
+        Ok(SumoReq {
+            motor_l: next(fields)?,
+            motor_r: next(fields)?,
+        })
+    };
+    let reqs = [req()?, req()?];
+    Ok(Frame {
+        round,
+        tick,
+        states,
+        sensors,
+        reqs,
+    })
+}
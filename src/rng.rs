@@ -0,0 +1,56 @@
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Generador de números pseudoaleatorios (SplitMix64)
+//
+// No es criptográfico ni falta que hace: lo importante es que, con la misma semilla, saque siempre
+// los mismos números, para que cualquier simulación se pueda repetir exactamente
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    // Un generador independiente derivado de este, útil para dar a cada robot (o a cada
+    // combate) su propia secuencia sin que dependa del orden en que se piden los números
+    pub fn fork(&self, stream: u64) -> Rng {
+        let mut rng = Rng::new(self.state ^ stream.wrapping_mul(0xA24B_AED4_963E_E407));
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Número uniforme en [0, 1)
+    pub fn uniform(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    // Número uniforme en [min, max)
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.uniform()
+    }
+
+    // `true` con probabilidad `p`
+    pub fn chance(&mut self, p: f32) -> bool {
+        self.uniform() < p
+    }
+
+    // Número con distribución normal de media 0 y desviación `std` (Box-Muller)
+    pub fn gaussian(&mut self, std: f32) -> f32 {
+        let u1 = 1.0 - self.uniform(); // en (0, 1], para no hacer ln(0)
+        let u2 = self.uniform();
+        std * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+    }
+}
@@ -0,0 +1,149 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use crate::{
+    config::{parse, parse_non_negative, parse_positive},
+    SumoReq,
+};
+
+// =======================================================================================================
+// Constantes
+// =======================================================================================================
+
+const G: f32 = 9.81; // Aceleración de la gravedad (m/s²)
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Características físicas de cada robot, que pueden ser distintas para cada uno
+//
+// Los motores no obedecen al instante: lo que pide la estrategia pasa por una zona muerta, se
+// limita a la velocidad máxima, se suaviza con un retardo de primer orden y, por último, no puede
+// cambiar más rápido de lo que permiten la aceleración y la deceleración
+//
+// Las ruedas tampoco se agarran perfectamente al tatami: hacia delante empujan como mucho con
+// `push_force` (si hace falta más, patinan) y de lado aguantan como mucho `grip_force` (si empujan
+// al robot de lado con más fuerza, derrapa). Así, cuando dos robots se empujan, lo que cuenta es
+// su masa y cuánta fuerza pueden hacer contra el suelo
+#[derive(Clone, Debug)]
+pub struct RobotParams {
+    pub max_speed: f32,        // velocidad máxima de cada rueda (rad/s)
+    pub accel: f32,            // aceleración máxima de cada rueda (rad/s²)
+    pub decel: f32,            // deceleración máxima, al frenar o dar marcha atrás (rad/s²)
+    pub motor_lag: f32,        // constante de tiempo del motor (s, 0 significa inmediato)
+    pub deadband: f32,         // por debajo de esta velocidad pedida, el motor no se mueve (rad/s)
+    pub mass: f32,             // masa del robot (kg)
+    pub wheel_friction: f32,   // coeficiente de rozamiento de las ruedas con el tatami
+    pub lateral_friction: f32, // coeficiente de rozamiento de las ruedas cuando el robot va de lado
+    pub torque: f32,           // par máximo de los motores, sumando las dos ruedas (N·m)
+    pub wheel_radius: f32,     // radio de las ruedas (m)
+    pub wheel_base: f32,       // distancia entre las dos ruedas (m, 0 significa el lado del robot)
+}
+
+impl Default for RobotParams {
+    // Por defecto, motores perfectos: hacen exactamente lo que se les pide
+    fn default() -> Self {
+        RobotParams {
+            max_speed: f32::INFINITY,
+            accel: f32::INFINITY,
+            decel: f32::INFINITY,
+            motor_lag: 0.0,
+            deadband: 0.0,
+            mass: 3.0,
+            wheel_friction: 1.0,
+            lateral_friction: 1.0,
+            torque: 1.0,
+            wheel_radius: 0.03,
+            wheel_base: 0.0,
+        }
+    }
+}
+
+impl RobotParams {
+    // Cambia un parámetro a partir de su nombre, devuelve `false` si no es un parámetro de robot
+    pub fn set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        match key {
+            "max_speed" => self.max_speed = parse_non_negative(key, value)?,
+            "accel" => self.accel = parse_non_negative(key, value)?,
+            "decel" => self.decel = parse_non_negative(key, value)?,
+            "motor_lag" => self.motor_lag = parse_non_negative(key, value)?,
+            "deadband" => self.deadband = parse_non_negative(key, value)?,
+            "mass" => self.mass = parse_positive(key, value)?,
+            "wheel_friction" => self.wheel_friction = parse_non_negative(key, value)?,
+            "lateral_friction" => self.lateral_friction = parse_non_negative(key, value)?,
+            "torque" => self.torque = parse_non_negative(key, value)?,
+            "wheel_radius" => self.wheel_radius = parse_positive(key, value)?,
+            "wheel_base" => self.wheel_base = parse(key, value)?,
+            _ => return Ok(false),
+        };
+        Ok(true)
+    }
+
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("max_speed", self.max_speed.to_string()),
+            ("accel", self.accel.to_string()),
+            ("decel", self.decel.to_string()),
+            ("motor_lag", self.motor_lag.to_string()),
+            ("deadband", self.deadband.to_string()),
+            ("mass", self.mass.to_string()),
+            ("wheel_friction", self.wheel_friction.to_string()),
+            ("lateral_friction", self.lateral_friction.to_string()),
+            ("torque", self.torque.to_string()),
+            ("wheel_radius", self.wheel_radius.to_string()),
+            ("wheel_base", self.wheel_base.to_string()),
+        ]
+    }
+
+    // La distancia entre las ruedas de un robot de lado `size`
+    pub fn base(&self, size: f32) -> f32 {
+        if self.wheel_base > 0.0 {
+            self.wheel_base
+        } else {
+            size
+        }
+    }
+
+    // La fuerza máxima con la que el robot puede empujar (o resistir un empujón): la que dan los
+    // motores, salvo que sea más de la que aguantan las ruedas sin patinar (rozamiento por peso)
+    pub fn push_force(&self) -> f32 {
+        (self.torque / self.wheel_radius).min(self.wheel_friction * self.mass * G)
+    }
+
+    // La fuerza máxima con la que las ruedas aguantan que el robot se mueva de lado
+    pub fn grip_force(&self) -> f32 {
+        self.lateral_friction * self.mass * G
+    }
+
+    // Dada la velocidad actual de las ruedas y la que pide la estrategia, calcula la velocidad
+    // de las ruedas tras `dt` segundos
+    pub fn motor_step(&self, current: SumoReq, requested: SumoReq, dt: f32) -> SumoReq {
+        let wheel = |current: f32, requested: f32| {
+            let target = if requested.abs() < self.deadband {
+                0.0
+            } else {
+                requested.clamp(-self.max_speed, self.max_speed)
+            };
+            // retardo de primer orden: nos acercamos al objetivo una fracción cada paso
+            let alpha = if self.motor_lag > 0.0 {
+                1.0 - (-dt / self.motor_lag).exp()
+            } else {
+                1.0
+            };
+            let wanted = current + (target - current) * alpha;
+            // acelerar es alejarse de 0 sin cambiar de sentido, todo lo demás es frenar
+            let limit = if wanted * current >= 0.0 && wanted.abs() > current.abs() {
+                self.accel * dt
+            } else {
+                self.decel * dt
+            };
+            current + (wanted - current).clamp(-limit, limit)
+        };
+        SumoReq {
+            motor_l: wheel(current.motor_l, requested.motor_l),
+            motor_r: wheel(current.motor_r, requested.motor_r),
+        }
+    }
+}
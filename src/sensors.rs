@@ -0,0 +1,352 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::{collections::VecDeque, f32::consts::PI};
+
+use crate::{rng::Rng, SensorFrame, SimConfig, SumoState, Vec2, ORIGIN};
+
+// =======================================================================================================
+// Constantes
+// =======================================================================================================
+
+const CONE_RAYS: usize = 9; // Rayos que lanzamos para cubrir el cono del sensor
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Un sensor de distancia por ultrasonidos montado en el robot
+//
+// El sensor no ve una línea, ve un cono: cualquier cosa dentro del cono y más cerca que su alcance
+// devuelve eco. Lo simulamos lanzando varios rayos repartidos por el cono y quedándonos con el
+// choque más cercano
+#[derive(Clone, Copy, Debug)]
+pub struct Sensor {
+    pub pos: Vec2,       // posición respecto al centro del robot (con el robot mirando hacia `x`)
+    pub angle: f32,      // hacia dónde mira, respecto a la dirección del robot
+    pub half_angle: f32, // mitad de la apertura del cono
+    pub range: f32,      // distancia máxima a la que ve algo
+}
+
+impl Sensor {
+    // Distancia desde el sensor de `me` hasta el primer punto de `other` que toque alguno de los
+    // rayos del cono, o 0 si no ve nada (como un sensor de verdad, si tiene al rival pegado
+    // también lee 0)
+    pub fn read(&self, me: SumoState, other: SumoState) -> f32 {
+        let origin = me.center + self.pos.rotate(me.dir);
+        (0..CONE_RAYS)
+            .map(|i| {
+                let spread = i as f32 / (CONE_RAYS - 1) as f32 * 2.0 - 1.0; // de -1 a 1
+                let theta = me.dir + self.angle + spread * self.half_angle;
+                raycast(origin, Vec2 { x: theta.cos(), y: theta.sin() }, other)
+            })
+            .filter(|&d| d <= self.range)
+            .fold(None, |best: Option<f32>, d| Some(best.map_or(d, |b| b.min(d))))
+            .unwrap_or(0.0)
+    }
+}
+
+// Un sensor de línea: mira hacia abajo y detecta si está sobre el borde blanco del tatami
+#[derive(Clone, Copy, Debug)]
+pub struct LineSensor {
+    pub pos: Vec2, // posición respecto al centro del robot (con el robot mirando hacia `x`)
+}
+
+impl LineSensor {
+    // `true` si el sensor está sobre el anillo blanco (fuera del tatami no hay nada que ver)
+    pub fn read(&self, me: SumoState, config: &SimConfig) -> bool {
+        let d = (me.center + self.pos.rotate(me.dir)).dist(ORIGIN);
+        d >= config.tatami_size - config.border_width && d <= config.tatami_size
+    }
+}
+
+// Todos los sensores de un robot
+#[derive(Clone, Debug)]
+pub struct SensorLayout {
+    pub distance: Vec<Sensor>,
+    pub line: Vec<LineSensor>,
+}
+
+impl SensorLayout {
+    // Los sensores del robot `robot` (0 o 1) según la configuración
+    pub fn for_robot(robot: usize, config: &SimConfig) -> Result<SensorLayout, String> {
+        Ok(SensorLayout {
+            distance: layout(&config.sensors[robot], config)?,
+            line: line_layout(&config.line_sensors[robot], config)?,
+        })
+    }
+
+    // Lee todos los sensores de `me` mirando a `other`
+    pub fn sense(&self, me: SumoState, other: SumoState, config: &SimConfig) -> SensorFrame {
+        SensorFrame {
+            distance: self.distance.iter().map(|sensor| sensor.read(me, other)).collect(),
+            line: self.line.iter().map(|sensor| sensor.read(me, config)).collect(),
+        }
+    }
+}
+
+// Los sensores de verdad no son perfectos: esto estropea las lecturas exactas de un robot para
+// parecerse más a lo que verá en la realidad. Cada capa se activa en la configuración:
+//
+// - `false_positive`: probabilidad de que un sensor que no ve nada vea algo (a una distancia
+//   cualquiera), o de que un sensor de línea vea borde donde no lo hay
+// - `dropout`: probabilidad de que un sensor que ve algo no lo vea, o de que un sensor de línea
+//   no vea el borde
+// - `noise_std`: desviación del ruido gaussiano que se suma a cada distancia
+// - `quantization`: resolución de las distancias (se redondean a múltiplos de este valor)
+// - `sensor_range`: las distancias que, con todo lo anterior, queden fuera de alcance se pierden
+// - `latency`: la estrategia recibe lo que vieron los sensores hace estos segundos (redondeado a
+//   pasos enteros)
+//
+// Los números aleatorios salen de un generador con semilla, así que la misma simulación siempre
+// se estropea igual
+pub struct SensorNoise {
+    rng: Rng,
+    history: VecDeque<SensorFrame>, // lecturas que todavía no han "llegado" a la estrategia
+}
+
+impl SensorNoise {
+    pub fn new(rng: Rng) -> SensorNoise {
+        SensorNoise {
+            rng,
+            history: VecDeque::new(),
+        }
+    }
+
+    // Al empezar una ronda se olvidan las lecturas pendientes
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    pub fn apply(&mut self, exact: SensorFrame, config: &SimConfig) -> SensorFrame {
+        let rng = &mut self.rng;
+        let distance = exact
+            .distance
+            .into_iter()
+            .map(|d| {
+                let d = if d == 0.0 {
+                    if rng.chance(config.false_positive) {
+                        rng.range(0.0, config.sensor_range)
+                    } else {
+                        return 0.0;
+                    }
+                } else if rng.chance(config.dropout) {
+                    return 0.0;
+                } else {
+                    d
+                };
+                let d = d + rng.gaussian(config.noise_std);
+                let d = if config.quantization > 0.0 {
+                    (d / config.quantization).round() * config.quantization
+                } else {
+                    d
+                };
+                if d > 0.0 && d <= config.sensor_range {
+                    d
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let line = exact
+            .line
+            .into_iter()
+            .map(|l| {
+                if l {
+                    !rng.chance(config.dropout)
+                } else {
+                    rng.chance(config.false_positive)
+                }
+            })
+            .collect();
+
+        // la latencia: guardamos la lectura y devolvemos la de hace `latency` segundos (o la más
+        // antigua que tengamos si acabamos de empezar)
+        let delay = (config.latency / config.dt).round() as usize;
+        self.history.push_back(SensorFrame { distance, line });
+        while self.history.len() > delay + 1 {
+            self.history.pop_front();
+        }
+        self.history[0].clone()
+    }
+}
+
+// Construye la lista de sensores de un robot a partir de su descripción, que puede ser el nombre
+// de una de las disposiciones típicas (para un robot de lado `size`):
+//
+// | nombre  | sensores                                                        |
+// |---------|-----------------------------------------------------------------|
+// | `front` | uno en el centro del frontal                                    |
+// | `three` | delantero izquierdo, delantero y delantero derecho              |
+// | `five`  | izquierdo, delantero izquierdo, delantero, delantero derecho y  |
+// |         | derecho                                                         |
+//
+// o una lista de sensores separados por `;`, cada uno como `x y ángulo` (posición respecto al
+// centro con el robot mirando hacia `x`, y hacia dónde mira en grados, positivo a la izquierda)
+//
+// Todos los sensores usan la apertura y el alcance de la configuración. Siempre van ordenados de
+// izquierda a derecha
+pub fn layout(spec: &str, config: &SimConfig) -> Result<Vec<Sensor>, String> {
+    let h = config.sumo_size / 2.0;
+    let sensor = |x: f32, y: f32, degrees: f32| Sensor {
+        pos: Vec2 { x, y },
+        angle: degrees * PI / 180.0,
+        half_angle: config.sensor_half_angle,
+        range: config.sensor_range,
+    };
+    match spec.trim() {
+        "front" => Ok(vec![sensor(h, 0.0, 0.0)]),
+        "three" => Ok(vec![
+            sensor(h, 0.6 * h, 30.0),
+            sensor(h, 0.0, 0.0),
+            sensor(h, -0.6 * h, -30.0),
+        ]),
+        "five" => Ok(vec![
+            sensor(0.0, h, 90.0),
+            sensor(h, 0.6 * h, 30.0),
+            sensor(h, 0.0, 0.0),
+            sensor(h, -0.6 * h, -30.0),
+            sensor(0.0, -h, -90.0),
+        ]),
+        custom => custom
+            .split(';')
+            .map(|s| {
+                let v = s
+                    .split_whitespace()
+                    .map(|n| n.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("sensor inválido: `{}`", s.trim()))?;
+                match v[..] {
+                    [x, y, degrees] => Ok(sensor(x, y, degrees)),
+                    _ => Err(format!("se esperaba `x y ángulo`: `{}`", s.trim())),
+                }
+            })
+            .collect(),
+    }
+}
+
+// Igual que `layout` pero para los sensores de línea:
+//
+// | nombre    | sensores                                   |
+// |-----------|--------------------------------------------|
+// | `none`    | ninguno                                    |
+// | `front`   | en las dos esquinas delanteras             |
+// | `corners` | en las cuatro esquinas                     |
+//
+// o una lista de posiciones `x y` separadas por `;`. Van ordenados de izquierda a derecha y de
+// delante a atrás
+pub fn line_layout(spec: &str, config: &SimConfig) -> Result<Vec<LineSensor>, String> {
+    let h = config.sumo_size / 2.0;
+    let sensor = |x: f32, y: f32| LineSensor { pos: Vec2 { x, y } };
+    match spec.trim() {
+        "none" => Ok(vec![]),
+        "front" => Ok(vec![sensor(h, h), sensor(h, -h)]),
+        "corners" => Ok(vec![sensor(h, h), sensor(h, -h), sensor(-h, h), sensor(-h, -h)]),
+        custom => custom
+            .split(';')
+            .map(|s| {
+                let v = s
+                    .split_whitespace()
+                    .map(|n| n.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| format!("sensor de línea inválido: `{}`", s.trim()))?;
+                match v[..] {
+                    [x, y] => Ok(sensor(x, y)),
+                    _ => Err(format!("se esperaba `x y`: `{}`", s.trim())),
+                }
+            })
+            .collect(),
+    }
+}
+
+// Lanza un rayo desde `origin` en la dirección `dir` (de longitud 1) y devuelve a qué distancia
+// choca con el robot `target`, o infinito si no choca
+//
+// Pasamos el rayo al sistema de coordenadas del robot, donde es un cuadrado sin girar centrado en
+// el origen, y buscamos cuándo entra el rayo en la franja de `x` y en la de `y` (método de las
+// franjas). Si está dentro de las dos a la vez, ha chocado
+fn raycast(origin: Vec2, dir: Vec2, target: SumoState) -> f32 {
+    let o = (origin - target.center).rotate(-target.dir);
+    let d = dir.rotate(-target.dir);
+    let h = target.size / 2.0;
+    let (mut t_in, mut t_out) = (0.0f32, f32::INFINITY);
+    for (o, d) in [(o.x, d.x), (o.y, d.y)] {
+        if d.abs() < f32::EPSILON {
+            if o.abs() > h {
+                return f32::INFINITY; // paralelo a la franja y fuera de ella, nunca entra
+            }
+        } else {
+            let (t1, t2) = ((-h - o) / d, (h - o) / d);
+            t_in = t_in.max(t1.min(t2));
+            t_out = t_out.min(t1.max(t2));
+        }
+    }
+    if t_in <= t_out {
+        t_in
+    } else {
+        f32::INFINITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Un robot de 1 m de lado
+    fn robot(x: f32, y: f32, dir: f32) -> SumoState {
+        SumoState::new(Vec2 { x, y }, dir, 1.0)
+    }
+
+    fn sensor(range: f32) -> Sensor {
+        Sensor {
+            pos: Vec2 { x: 0.5, y: 0.0 }, // en el centro del frontal
+            angle: 0.0,
+            half_angle: 0.26,
+            range,
+        }
+    }
+
+    // El rayo choca con el lado más cercano, también si el robot está girado (con una esquina)
+    #[test]
+    fn raycast_hit() {
+        let (origin, dir) = (Vec2 { x: -2.0, y: 0.0 }, Vec2 { x: 1.0, y: 0.0 });
+        assert!((raycast(origin, dir, robot(0.0, 0.0, 0.0)) - 1.5).abs() < 1e-5);
+        let corner = 2.0 - 0.5 * 2f32.sqrt();
+        assert!((raycast(origin, dir, robot(0.0, 0.0, PI / 4.0)) - corner).abs() < 1e-5);
+    }
+
+    // Un rayo que pasa de largo o que apunta hacia el otro lado no choca
+    #[test]
+    fn raycast_miss() {
+        let origin = Vec2 { x: -2.0, y: 0.0 };
+        assert_eq!(raycast(origin, Vec2 { x: 0.0, y: 1.0 }, robot(0.0, 0.0, 0.0)), f32::INFINITY);
+        assert_eq!(raycast(origin, Vec2 { x: -1.0, y: 0.0 }, robot(0.0, 0.0, 0.0)), f32::INFINITY);
+    }
+
+    // Desde dentro del robot el choque es inmediato
+    #[test]
+    fn raycast_from_inside() {
+        let origin = Vec2 { x: 0.2, y: -0.1 };
+        assert_eq!(raycast(origin, Vec2 { x: 1.0, y: 0.0 }, robot(0.0, 0.0, 0.0)), 0.0);
+    }
+
+    // El sensor lee la distancia desde su posición en el robot, y 0 si no ve nada
+    #[test]
+    fn read_distance() {
+        let (me, other) = (robot(-1.0, 0.0, 0.0), robot(1.0, 0.0, 0.0));
+        assert!((sensor(1.5).read(me, other) - 1.0).abs() < 1e-5);
+        assert_eq!(sensor(0.5).read(me, other), 0.0); // fuera de alcance
+        assert_eq!(sensor(1.5).read(robot(-1.0, 0.0, PI), other), 0.0); // de espaldas
+    }
+
+    // El cono ve al rival aunque el rayo del centro pase de largo
+    #[test]
+    fn read_cone() {
+        let (me, other) = (robot(-1.0, 0.0, 0.0), robot(1.0, 0.6, 0.0));
+        let center = raycast(Vec2 { x: -0.5, y: 0.0 }, Vec2 { x: 1.0, y: 0.0 }, other);
+        assert_eq!(center, f32::INFINITY);
+        let d = sensor(1.5).read(me, other);
+        assert!(d > 1.0 && d <= 1.5, "{}", d);
+    }
+}
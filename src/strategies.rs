@@ -0,0 +1,299 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::cmp::Ordering;
+
+use crate::{config::parse, SensorFrame, Strategy, SumoReq, SumoStrategy, TickInfo};
+
+// =======================================================================================================
+// Constantes
+// =======================================================================================================
+
+// Los parámetros que se pueden ajustar de cada estrategia, con su valor por defecto y el rango en
+// el que tiene sentido buscarlos
+const CHARGE_PARAMS: &[Param] = &[
+    Param::new("full_speed", 4.0, 32.0, 16.0), // velocidad de las ruedas a tope (rad/s, unos
+                                               // 0.5 m/s con ruedas de 3 cm)
+];
+const SEEK_PARAMS: &[Param] = &[
+    Param::new("full_speed", 4.0, 32.0, 16.0), // velocidad de las ruedas a tope (rad/s)
+    Param::new("turn_speed", 1.0, 16.0, 8.0),  // velocidad de las ruedas al girar buscando al
+                                               // rival (rad/s)
+    Param::new("turn_gain", 0.0, 1.0, 0.5),    // cuánto frena la rueda de dentro para corregir
+                                               // hacia el rival (0 nada, 1 del todo)
+    Param::new("attack_range", 0.0, 4.0, 4.0), // a partir de qué distancia va a por el rival a
+                                               // tope (m, más lejos se acerca a media velocidad)
+];
+const WARY_PARAMS: &[Param] = &[
+    Param::new("full_speed", 4.0, 32.0, 16.0),
+    Param::new("turn_speed", 1.0, 16.0, 8.0),
+    Param::new("turn_gain", 0.0, 1.0, 0.5),
+    Param::new("attack_range", 0.0, 4.0, 4.0),
+    Param::new("back_off_time", 0.0, 1.0, 0.4), // segundos marcha atrás al ver el borde
+];
+
+// Las estrategias que vienen con el simulador, por nombre, con sus parámetros. Para añadir una
+// nueva basta con escribirla aquí abajo y apuntarla en esta lista
+const REGISTRY: &[(&str, &[Param], Constructor)] = &[
+    ("idle", &[], |_| Box::new(Idle)),
+    ("charge", CHARGE_PARAMS, |p| Box::new(Charge { full_speed: p[0] })),
+    ("seek", SEEK_PARAMS, |p| Box::new(Seek::new(p, None))),
+    ("wary", WARY_PARAMS, |p| Box::new(Seek::new(p, Some(p[4])))),
+];
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+type Constructor = fn(&[f32]) -> Strategy; // crea la estrategia lista para empezar un combate, con
+                                           // los valores de sus parámetros en orden
+
+// Un parámetro ajustable de una estrategia
+#[derive(Clone, Copy, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+}
+
+impl Param {
+    const fn new(name: &'static str, min: f32, max: f32, default: f32) -> Param {
+        Param { name, min, max, default }
+    }
+}
+
+// Los nombres de todas las estrategias registradas, en orden
+pub fn names() -> Vec<&'static str> {
+    REGISTRY.iter().map(|&(name, _, _)| name).collect()
+}
+
+// Los parámetros que acepta una estrategia registrada
+pub fn params(name: &str) -> Result<&'static [Param], String> {
+    REGISTRY
+        .iter()
+        .find(|&&(n, _, _)| n == name)
+        .map(|&(_, params, _)| params)
+        .ok_or_else(|| {
+            format!("no existe la estrategia `{}` (hay: {})", name, names().join(", "))
+        })
+}
+
+// El nombre completo de una estrategia con sus parámetros (`seek:full_speed=12,turn_gain=0.3`), tal
+// y como lo entiende `by_name`
+pub fn spec(name: &str, values: &[f32]) -> Result<String, String> {
+    let params = params(name)?;
+    let values: Vec<String> = params
+        .iter()
+        .zip(values)
+        .map(|(param, value)| format!("{}={}", param.name, (value * 1000.0).round() / 1000.0))
+        .collect();
+    Ok(if values.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}:{}", name, values.join(","))
+    })
+}
+
+// Una estrategia nueva (sin memoria de combates anteriores) a partir de su nombre, opcionalmente
+// con algunos de sus parámetros cambiados (`seek:turn_speed=6,attack_range=0.5`); el resto se
+// quedan con su valor por defecto
+pub fn by_name(spec: &str) -> Result<Strategy, String> {
+    let (name, values) = values(spec)?;
+    let new = REGISTRY.iter().find(|&&(n, _, _)| n == name).unwrap().2; // ya la encontró `values`
+    let strategy = new(&values);
+    let full_name = canonical(spec)?;
+    Ok(if full_name == name {
+        strategy
+    } else {
+        Box::new(Named {
+            name: full_name,
+            inner: strategy,
+        })
+    })
+}
+
+// La forma única de escribir una estrategia con parámetros: sin espacios y solo con los
+// parámetros que no tienen su valor por defecto, en el orden en que se declaran. Así
+// `seek: turn_speed = 6` y `seek:full_speed=16,turn_speed=6` son la misma estrategia
+pub fn canonical(spec: &str) -> Result<String, String> {
+    let (name, values) = values(spec)?;
+    let changed: Vec<String> = params(name)?
+        .iter()
+        .zip(&values)
+        .filter(|&(param, &value)| value != param.default)
+        .map(|(param, value)| format!("{}={}", param.name, value))
+        .collect();
+    Ok(if changed.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}:{}", name, changed.join(","))
+    })
+}
+
+// El nombre de una estrategia escrita como en `by_name` y los valores de todos sus parámetros
+fn values(spec: &str) -> Result<(&str, Vec<f32>), String> {
+    let (name, settings) = spec.split_once(':').unwrap_or((spec, ""));
+    let name = name.trim();
+    let params = params(name)?;
+    let mut values: Vec<f32> = params.iter().map(|param| param.default).collect();
+    for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("se esperaba `parámetro=valor` en `{}`", setting))?;
+        let n = params.iter().position(|param| param.name == key.trim()).ok_or_else(|| {
+            let known: Vec<&str> = params.iter().map(|param| param.name).collect();
+            format!("`{}` no tiene el parámetro `{}` (tiene: {})", name, key.trim(), known.join(", "))
+        })?;
+        let (param, value) = (params[n], parse::<f32>(key.trim(), value.trim())?);
+        if !(param.min..=param.max).contains(&value) {
+            return Err(format!(
+                "`{}` tiene que estar entre {} y {} (es {})",
+                param.name, param.min, param.max, value
+            ));
+        }
+        values[n] = value;
+    }
+    Ok((name, values))
+}
+
+// Una estrategia con parámetros cambiados, que aparece con su nombre completo en las repeticiones
+struct Named {
+    name: String,
+    inner: Strategy,
+}
+
+impl SumoStrategy for Named {
+    fn act(&mut self, sensors: &SensorFrame, tick: &TickInfo) -> SumoReq {
+        self.inner.act(sensors, tick)
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn wheels(motor_l: f32, motor_r: f32) -> SumoReq {
+    SumoReq { motor_l, motor_r }
+}
+
+// Se queda quieta, útil como saco de arena
+struct Idle;
+
+impl SumoStrategy for Idle {
+    fn act(&mut self, _: &SensorFrame, _: &TickInfo) -> SumoReq {
+        wheels(0.0, 0.0)
+    }
+
+    fn name(&self) -> &str {
+        "idle"
+    }
+}
+
+// En cuanto dan la salida, todo recto a tope
+struct Charge {
+    full_speed: f32,
+}
+
+impl SumoStrategy for Charge {
+    fn act(&mut self, _: &SensorFrame, tick: &TickInfo) -> SumoReq {
+        if tick.started {
+            wheels(self.full_speed, self.full_speed)
+        } else {
+            wheels(0.0, 0.0)
+        }
+    }
+
+    fn name(&self) -> &str {
+        "charge"
+    }
+}
+
+// Gira sobre sí misma hasta ver al rival y entonces va a por él, corrigiendo hacia el lado del
+// sensor que lo vea más cerca (a tope si está a menos de `attack_range`, a media velocidad si
+// no). La versión `wary` además da marcha atrás `back_off_time` segundos si algún sensor de línea
+// ve el borde
+struct Seek {
+    full_speed: f32,
+    turn_speed: f32,
+    turn_gain: f32,
+    attack_range: f32,
+    back_off_time: Option<f32>, // solo en la versión `wary`
+    backing: Option<f32>,       // hasta cuándo seguir marcha atrás (segundos de la ronda)
+}
+
+impl Seek {
+    // A partir de los valores de `SEEK_PARAMS` (los primeros de `WARY_PARAMS` son los mismos)
+    fn new(params: &[f32], back_off_time: Option<f32>) -> Seek {
+        Seek {
+            full_speed: params[0],
+            turn_speed: params[1],
+            turn_gain: params[2],
+            attack_range: params[3],
+            back_off_time,
+            backing: None,
+        }
+    }
+}
+
+impl SumoStrategy for Seek {
+    fn act(&mut self, sensors: &SensorFrame, tick: &TickInfo) -> SumoReq {
+        if !tick.started {
+            return wheels(0.0, 0.0);
+        }
+        if let Some(back_off_time) = self.back_off_time {
+            if self.backing.is_none() && sensors.line.contains(&true) {
+                self.backing = Some(tick.time + back_off_time);
+            }
+        }
+        if let Some(until) = self.backing {
+            if tick.time < until {
+                // marcha atrás girando un poco
+                return wheels(-self.full_speed, -self.full_speed / 2.0);
+            }
+            self.backing = None;
+        }
+
+        // el sensor que ve al rival más cerca (los sensores van de izquierda a derecha)
+        let closest = sensors
+            .distance
+            .iter()
+            .enumerate()
+            .filter(|&(_, &d)| d > 0.0)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        let middle = sensors.distance.len() / 2;
+        let (i, &distance) = match closest {
+            Some(closest) => closest,
+            None => return wheels(-self.turn_speed, self.turn_speed), // no lo vemos, lo buscamos
+                                                                      // girando
+        };
+        let speed = if distance <= self.attack_range {
+            self.full_speed
+        } else {
+            self.full_speed / 2.0
+        };
+        let inner = speed * (1.0 - self.turn_gain); // la rueda del lado hacia el que giramos
+        match i.cmp(&middle) {
+            Ordering::Less => wheels(inner, speed),
+            Ordering::Greater => wheels(speed, inner),
+            Ordering::Equal => wheels(speed, speed),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.backing = None;
+    }
+
+    fn name(&self) -> &str {
+        if self.back_off_time.is_some() {
+            "wary"
+        } else {
+            "seek"
+        }
+    }
+}
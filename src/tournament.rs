@@ -0,0 +1,133 @@
+// =======================================================================================================
+// Importes de librerías
+// =======================================================================================================
+
+use std::fmt;
+
+use crate::{run_batch, strategies, Job, MatchResult, SimConfig};
+
+// =======================================================================================================
+// Código
+// =======================================================================================================
+
+// Cómo le ha ido a una estrategia en el torneo
+#[derive(Clone, Debug)]
+pub struct Standing {
+    pub name: String,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    pub time: f32, // segundos jugados, sumando todos sus combates
+}
+
+impl Standing {
+    pub fn matches(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    // Lo que dura de media uno de sus combates
+    pub fn average_time(&self) -> f32 {
+        self.time / self.matches().max(1) as f32
+    }
+}
+
+// La clasificación final, de mejor a peor
+pub struct Ranking(pub Vec<Standing>);
+
+// Todos contra todos: cada pareja de estrategias juega `matches` combates con cada una en cada
+// posición (como robot 1 y como robot 2), cada combate con su propia semilla, repartidos entre
+// `threads` hilos (ver `run_batch`). Tras cada combate llama a `on_match` con las dos estrategias
+// y el resultado
+//
+// Cada estrategia solo puede aparecer una vez (contando como la misma las que solo se diferencian
+// en cómo están escritas, ver `strategies::canonical`)
+pub fn tournament(
+    names: &[&str],
+    matches: u32,
+    threads: usize,
+    config: &SimConfig,
+    mut on_match: impl FnMut([&str; 2], &MatchResult),
+) -> Result<Ranking, String> {
+    let canonical = names
+        .iter()
+        .map(|&name| strategies::canonical(name))
+        .collect::<Result<Vec<String>, String>>()?;
+    for (n, name) in canonical.iter().enumerate() {
+        if canonical[..n].contains(name) {
+            return Err(format!("`{}` está dos veces en el torneo", name));
+        }
+    }
+
+    let mut jobs = Vec::new();
+    for a in 0..names.len() {
+        for b in a + 1..names.len() {
+            for (first, second) in [(a, b), (b, a)] {
+                for n in 0..matches {
+                    jobs.push(Job {
+                        names: [names[first], names[second]],
+                        config: SimConfig {
+                            seed: config.seed.wrapping_add(n as u64),
+                            ..config.clone()
+                        },
+                    });
+                }
+            }
+        }
+    }
+    let results = run_batch(&jobs, threads)?;
+
+    let mut standings: Vec<Standing> = names
+        .iter()
+        .map(|&name| Standing {
+            name: name.to_string(),
+            wins: 0,
+            losses: 0,
+            draws: 0,
+            time: 0.0,
+        })
+        .collect();
+    for (job, result) in jobs.iter().zip(&results) {
+        on_match(job.names, result);
+        let time = result.rounds.iter().map(|r| r.time).sum::<f32>();
+        for (robot, name) in job.names.into_iter().enumerate() {
+            let standing = standings.iter_mut().find(|s| s.name == name).unwrap();
+            match result.winner {
+                Some(winner) if winner == robot => standing.wins += 1,
+                Some(_) => standing.losses += 1,
+                None => standing.draws += 1,
+            }
+            standing.time += time;
+        }
+    }
+    standings.sort_by(|a, b| {
+        (b.wins, b.draws, a.losses)
+            .cmp(&(a.wins, a.draws, b.losses))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(Ranking(standings))
+}
+
+impl fmt::Display for Ranking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.0.iter().map(|s| s.name.len()).chain([10]).max().unwrap_or(10);
+        writeln!(
+            f,
+            "{:>3}  {:<width$}  {:>8}  {:>8}  {:>8}  {:>8}  {:>12}",
+            "#", "estrategia", "combates", "ganados", "perdidos", "empates", "duración (s)"
+        )?;
+        for (position, s) in self.0.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>3}  {:<width$}  {:>8}  {:>8}  {:>8}  {:>8}  {:>12.2}",
+                position + 1,
+                s.name,
+                s.matches(),
+                s.wins,
+                s.losses,
+                s.draws,
+                s.average_time()
+            )?;
+        }
+        Ok(())
+    }
+}